@@ -0,0 +1,216 @@
+// src/backtrace.rs
+//! 解析Rust原生调用栈
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+
+// 捕获调用栈本身及本库的函数，只出现在最内层
+const INTERNAL_PREFIXES: &[&str] = &[
+    "std::backtrace::",
+    "std::backtrace_rs::",
+    "backtrace::",
+    "pi_stash::",
+];
+
+// 运行时入口、线程启动、panic捕获和闭包调用的函数
+const RUNTIME_PREFIXES: &[&str] = &[
+    "std::rt::",
+    "std::panic::",
+    "std::panicking::",
+    "std::sys::",
+    "std::thread::",
+    "core::ops::function::",
+    "core::panic::",
+    "test::",
+    "tokio::runtime::",
+    "__rust_",
+    "__libc_start",
+];
+
+// 完整匹配的运行时函数，其中main是C语言入口，Rust的main带有crate路径
+const RUNTIME_SYMBOLS: &[&str] = &[
+    "main",
+    "_start",
+    "start_thread",
+    "clone",
+    "clone3",
+    "__clone",
+];
+
+/// 捕获调用栈时的可选参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BacktraceOptions {
+    /// 跳过最内层的捕获调用栈的函数和本库的函数
+    pub skip_internal: bool,
+    /// 跳过运行时入口、线程启动、panic捕获和闭包调用等函数
+    pub skip_runtime: bool,
+}
+
+impl BacktraceOptions {
+    /// 保留所有帧
+    pub const FULL: Self = Self {
+        skip_internal: false,
+        skip_runtime: false,
+    };
+}
+
+impl Default for BacktraceOptions {
+    /// 跳过本库和运行时的帧
+    fn default() -> Self {
+        Self {
+            skip_internal: true,
+            skip_runtime: true,
+        }
+    }
+}
+
+/// 将调用栈解析为每帧一个字符串，最内层在前
+///
+/// 每帧格式为 `函数名 (文件:行号)`，没有位置信息时只有函数名；
+/// 调用栈未捕获（如平台不支持）时返回空数组
+pub(crate) fn frames(backtrace: &Backtrace, options: BacktraceOptions) -> Vec<String> {
+    if backtrace.status() != BacktraceStatus::Captured {
+        return Vec::new();
+    }
+    let mut frames = parse(&backtrace.to_string());
+    skip(&mut frames, options);
+    frames
+        .into_iter()
+        .map(|(function, location)| match location {
+            Some(location) => format!("{function} ({location})"),
+            None => function,
+        })
+        .collect()
+}
+
+/// 解析 `Backtrace` 的Display输出为(函数名, 文件:行号)
+///
+/// 输出中每个符号一行，形如 `  12: 函数名`，内联的符号省略序号；
+/// 其后可能跟一行 `at 文件:行号:列号`
+fn parse(text: &str) -> Vec<(String, Option<String>)> {
+    let mut frames: Vec<(String, Option<String>)> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(location) = line.strip_prefix("at ") {
+            if let Some((_, slot @ None)) = frames.last_mut() {
+                *slot = Some(strip_column(location).to_string());
+            }
+            continue;
+        }
+        let function = match line.split_once(": ") {
+            Some((index, function)) if index.bytes().all(|b| b.is_ascii_digit()) => function,
+            _ => line,
+        };
+        frames.push((function.to_string(), None));
+    }
+    frames
+}
+
+/// 按选项移除帧
+fn skip(frames: &mut Vec<(String, Option<String>)>, options: BacktraceOptions) {
+    if options.skip_internal {
+        let internal = frames
+            .iter()
+            .take_while(|(function, _)| is_internal(function))
+            .count();
+        frames.drain(..internal);
+    }
+    if options.skip_runtime {
+        frames.retain(|(function, _)| !is_runtime(function));
+    }
+}
+
+/// 去掉位置末尾的列号
+fn strip_column(location: &str) -> &str {
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    match location.rsplit_once(':') {
+        Some((rest, column))
+            if is_number(column)
+                && rest
+                    .rsplit_once(':')
+                    .is_some_and(|(_, line)| is_number(line)) =>
+        {
+            rest
+        }
+        _ => location,
+    }
+}
+
+fn is_internal(function: &str) -> bool {
+    let function = function.trim_start_matches('<');
+    INTERNAL_PREFIXES
+        .iter()
+        .any(|prefix| function.starts_with(prefix))
+}
+
+fn is_runtime(function: &str) -> bool {
+    RUNTIME_SYMBOLS.contains(&function)
+        || function.contains(" as core::ops::function::Fn")
+        || RUNTIME_PREFIXES
+            .iter()
+            .any(|prefix| function.trim_start_matches('<').starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "   0: std::backtrace_rs::backtrace::libunwind::trace
+             at /rustc/abc/library/std/src/../../backtrace/src/backtrace/libunwind.rs:117:9
+   1: std::backtrace::Backtrace::create
+             at /rustc/abc/library/std/src/backtrace.rs:331:13
+   2: pi_stash::StackStore<K,V>::capture_backtrace
+             at ./src/lib.rs:100:9
+   3: app::render::draw
+             at ./src/render.rs:42:5
+      app::render::inlined
+             at ./src/render.rs:10:1
+   4: <alloc::boxed::Box<F,A> as core::ops::function::FnOnce<Args>>::call_once
+   5: app::main
+             at ./src/main.rs:7:5
+   6: std::rt::lang_start_internal
+             at /rustc/abc/library/std/src/rt.rs:174:48
+   7: main
+   8: __libc_start_main
+   9: _start
+";
+
+    #[test]
+    fn test_parse() {
+        let frames = parse(TEXT);
+        assert_eq!(frames.len(), 11);
+        assert_eq!(
+            frames[3],
+            (
+                "app::render::draw".to_string(),
+                Some("./src/render.rs:42".to_string())
+            )
+        );
+        assert_eq!(frames[4].0, "app::render::inlined");
+        assert_eq!(frames[5].1, None);
+        assert_eq!(strip_column("C:\\src\\a.rs:3:1"), "C:\\src\\a.rs:3");
+        assert_eq!(strip_column("a.rs:3"), "a.rs:3");
+    }
+
+    #[test]
+    fn test_skip() {
+        let mut frames = parse(TEXT);
+        skip(
+            &mut frames,
+            BacktraceOptions {
+                skip_internal: true,
+                skip_runtime: false,
+            },
+        );
+        assert_eq!(frames.len(), 8);
+        assert_eq!(frames[0].0, "app::render::draw");
+        skip(&mut frames, BacktraceOptions::default());
+        let functions: Vec<&str> = frames.iter().map(|(f, _)| f.as_str()).collect();
+        assert_eq!(
+            functions,
+            ["app::render::draw", "app::render::inlined", "app::main"]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+// src/budget.rs
+//! 全局内存预算及跨键淘汰
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 超出内存预算时选择淘汰栈的顺序
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionOrder {
+    /// 最久未被读写的栈优先淘汰
+    LeastRecentlyUsed,
+    /// 最久未被写入的栈优先淘汰
+    LeastRecentlyWritten,
+}
+
+/// 整个StackStore的内存预算
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryBudget {
+    /// 所有键和值的估算字节数上限
+    pub max_bytes: usize,
+    /// 超出上限时的淘汰顺序
+    pub order: EvictionOrder,
+    /// 淘汰的目标字节数：超出上限后一次淘汰到该值之下，使之后的多次压入不必再淘汰
+    pub low_water_bytes: usize,
+}
+
+impl MemoryBudget {
+    /// 创建内存预算，淘汰目标为上限的90%
+    pub fn new(max_bytes: usize, order: EvictionOrder) -> Self {
+        Self {
+            max_bytes,
+            order,
+            low_water_bytes: max_bytes - max_bytes / 10,
+        }
+    }
+
+    /// 设置淘汰的目标字节数，大于上限时按上限处理
+    pub fn with_low_water(mut self, bytes: usize) -> Self {
+        self.low_water_bytes = bytes;
+        self
+    }
+}
+
+/// 内存预算淘汰的统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvictionStats {
+    /// 被淘汰的栈个数
+    pub evicted_keys: u64,
+    /// 被淘汰的栈中值的个数
+    pub evicted_values: u64,
+    /// 被淘汰的估算字节数
+    pub evicted_bytes: u64,
+}
+
+/// 内存预算淘汰计数器，由StackStore内部并发累加
+#[derive(Debug, Default)]
+pub(crate) struct EvictionCounters {
+    pub(crate) evicted_keys: AtomicU64,
+    pub(crate) evicted_values: AtomicU64,
+    pub(crate) evicted_bytes: AtomicU64,
+}
+
+impl EvictionCounters {
+    /// 读取当前统计值
+    pub(crate) fn snapshot(&self) -> EvictionStats {
+        EvictionStats {
+            evicted_keys: self.evicted_keys.load(Ordering::Relaxed),
+            evicted_values: self.evicted_values.load(Ordering::Relaxed),
+            evicted_bytes: self.evicted_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 键和值的字节数估算函数
+pub(crate) struct Sizer<K, V> {
+    pub(crate) key: fn(&K) -> usize,
+    pub(crate) value: fn(&V) -> usize,
+}
+
+impl<K, V> Default for Sizer<K, V> {
+    fn default() -> Self {
+        Self {
+            key: std::mem::size_of_val::<K>,
+            value: std::mem::size_of_val::<V>,
+        }
+    }
+}
+
+/// 字符串的估算字节数：String本身加上堆上分配的容量
+#[allow(clippy::ptr_arg)]
+pub(crate) fn string_size(s: &String) -> usize {
+    std::mem::size_of::<String>() + s.capacity()
+}
+
+/// 驻留字符串的估算字节数：Arc本身加上字符串长度，共享的字符串按每个引用分别计算
+pub(crate) fn arc_str_size(s: &std::sync::Arc<str>) -> usize {
+    std::mem::size_of::<std::sync::Arc<str>>() + s.len()
+}
@@ -0,0 +1,105 @@
+// src/dump.rs
+//! 崩溃时导出存储内容
+
+use crate::{KeyFilter, GLOBAL_STACK_STORE};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::panic;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// 等待锁时每次重试的间隔
+const RETRY_INTERVAL: Duration = Duration::from_micros(100);
+
+/// 导出目标
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DumpTarget {
+    /// 写到标准错误输出
+    Stderr,
+    /// 写到文件，文件已存在时覆盖
+    File(PathBuf),
+}
+
+/// 导出结果统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DumpStats {
+    /// 导出的栈个数
+    pub stacks: usize,
+    /// 导出的值个数
+    pub values: usize,
+    /// 因锁被占用未能导出内容的栈个数，这些栈输出为 `[键名, null]`
+    pub locked_stacks: usize,
+    /// 因分片锁被占用整体跳过的分片个数，其中的键不会出现在输出中
+    pub locked_shards: usize,
+}
+
+/// 安装panic钩子，在panic时导出全局存储 `GLOBAL_STACK_STORE` 中匹配的栈
+///
+/// # 参数
+/// - target: 导出目标
+/// - filter: 键名过滤条件
+/// - lock_timeout: 等待锁的总时长上限，超时后被占用的栈和分片直接跳过
+///
+/// # 注意
+/// - 钩子先调用之前安装的钩子（默认输出panic信息），再导出存储内容；
+///   `panic = "abort"` 时同样会在终止进程前执行
+/// - 多个线程同时panic时只有一个线程导出，其它线程跳过
+/// - 导出格式与 `iter` 相同，已过期的栈和值不导出
+pub fn install_panic_hook(target: DumpTarget, filter: KeyFilter, lock_timeout: Duration) {
+    static DUMPING: AtomicBool = AtomicBool::new(false);
+
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        previous(info);
+        if DUMPING.swap(true, Ordering::Acquire) {
+            return;
+        }
+        let _ = target.write(&filter, lock_timeout);
+        DUMPING.store(false, Ordering::Release);
+    }));
+}
+
+impl DumpTarget {
+    /// 将全局存储导出到目标
+    fn write(&self, filter: &KeyFilter, lock_timeout: Duration) -> io::Result<DumpStats> {
+        match self {
+            DumpTarget::Stderr => {
+                let mut stderr = io::stderr().lock();
+                let stats = GLOBAL_STACK_STORE.dump(&mut stderr, filter, lock_timeout)?;
+                writeln!(stderr)?;
+                Ok(stats)
+            }
+            DumpTarget::File(path) => {
+                let mut writer = BufWriter::new(File::create(path)?);
+                let stats = GLOBAL_STACK_STORE.dump(&mut writer, filter, lock_timeout)?;
+                writer.flush()?;
+                Ok(stats)
+            }
+        }
+    }
+}
+
+/// 在截止时间前反复尝试，截止时间已过时只尝试一次
+pub(crate) fn try_until<T>(deadline: Instant, mut f: impl FnMut() -> Option<T>) -> Option<T> {
+    loop {
+        if let Some(value) = f() {
+            return Some(value);
+        }
+        if Instant::now() >= deadline {
+            return None;
+        }
+        thread::sleep(RETRY_INTERVAL);
+    }
+}
+
+/// 尝试加锁，锁被污染时从中恢复
+pub(crate) fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
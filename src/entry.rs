@@ -0,0 +1,240 @@
+// src/entry.rs
+//! 栈中的值及其元数据
+
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// 全局递增的压入序号，跨键、跨StackStore实例单调递增
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(1);
+
+// 全局递增的线程编号，每个线程首次压入时分配
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    // 当前线程的信息，每个线程只生成一次
+    static CURRENT_THREAD: Arc<ThreadInfo> = Arc::new(ThreadInfo::current());
+}
+
+/// 压入值的线程信息
+pub(crate) struct ThreadInfo {
+    pub(crate) id: u64,
+    pub(crate) name: Option<String>,
+}
+
+impl ThreadInfo {
+    fn current() -> Self {
+        let thread = std::thread::current();
+        Self {
+            id: NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed),
+            name: thread.name().map(str::to_string),
+        }
+    }
+}
+
+/// 栈中每个值附带的元数据
+pub(crate) struct EntryMeta {
+    // 压入时间，用于计算过期时间；游程编码合并时更新为最后一个值的压入时间
+    pub(crate) pushed_at: Instant,
+    // 存活时间，None表示不过期
+    pub(crate) ttl: Option<Duration>,
+    // 全局压入序号
+    pub(crate) seq: u64,
+    // 压入时的系统时间
+    pub(crate) timestamp: SystemTime,
+    // 压入值的线程
+    pub(crate) thread: Arc<ThreadInfo>,
+    // 用户元数据
+    pub(crate) metadata: BTreeMap<String, String>,
+    // 游程编码合并的重复次数，未合并时为1
+    pub(crate) count: usize,
+    // 由 `push_scoped` 压入，需要按序号单独移除，不与其它值合并
+    pub(crate) scoped: bool,
+}
+
+impl EntryMeta {
+    /// 以当前时间和当前线程创建元数据
+    pub(crate) fn new(ttl: Option<Duration>, metadata: BTreeMap<String, String>) -> Self {
+        Self {
+            pushed_at: Instant::now(),
+            ttl,
+            seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed),
+            timestamp: SystemTime::now(),
+            thread: CURRENT_THREAD.with(Arc::clone),
+            metadata,
+            count: 1,
+            scoped: false,
+        }
+    }
+
+    /// 新值能否合并到当前值中：两者的存活时间相同、都没有用户元数据，且都不是作用域值
+    pub(crate) fn can_merge(&self, other: &Self) -> bool {
+        !self.scoped
+            && !other.scoped
+            && self.ttl == other.ttl
+            && self.metadata.is_empty()
+            && other.metadata.is_empty()
+    }
+
+    /// 复制一份表示单个值的元数据
+    pub(crate) fn clone_single(&self) -> Self {
+        Self {
+            pushed_at: self.pushed_at,
+            ttl: self.ttl,
+            seq: self.seq,
+            timestamp: self.timestamp,
+            thread: Arc::clone(&self.thread),
+            metadata: self.metadata.clone(),
+            count: 1,
+            scoped: self.scoped,
+        }
+    }
+
+    /// 过期时间，None表示不过期
+    pub(crate) fn expires_at(&self) -> Option<Instant> {
+        self.ttl.map(|ttl| self.pushed_at + ttl)
+    }
+
+    /// 连同值生成对外的条目
+    pub(crate) fn to_entry<V>(&self, value: V) -> StackEntry<V> {
+        StackEntry {
+            value,
+            seq: self.seq,
+            timestamp: self.timestamp,
+            thread_id: self.thread.id,
+            thread_name: self.thread.name.clone(),
+            metadata: self.metadata.clone(),
+        }
+    }
+}
+
+/// 压入值时的可选参数
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryOptions {
+    /// 值的存活时间，None表示使用StackStore的默认存活时间
+    pub ttl: Option<Duration>,
+    /// 用户元数据
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl EntryOptions {
+    /// 设置值的存活时间
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// 添加一项用户元数据
+    pub fn meta(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// 栈中的值及其元数据
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackEntry<V> {
+    /// 压入的值
+    pub value: V,
+    /// 全局压入序号，跨键单调递增，可用于还原不同键之间的压入顺序
+    pub seq: u64,
+    /// 压入时的系统时间
+    pub timestamp: SystemTime,
+    /// 压入值的线程编号，由本库为每个线程分配，与 `ThreadId` 无关；线程结束后不会复用
+    pub thread_id: u64,
+    /// 压入值的线程名
+    pub thread_name: Option<String>,
+    /// 用户元数据
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// JSON输出中包含的元数据字段
+///
+/// 全部不包含时每个值直接输出为值本身，与 `get`/`iter` 相同；
+/// 否则每个值输出为一个对象，值在 `value` 字段中
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EntryFields {
+    /// 输出 `seq` 字段
+    pub seq: bool,
+    /// 输出 `timestamp` 字段，单位为Unix毫秒
+    pub timestamp: bool,
+    /// 输出 `thread_id` 和 `thread_name` 字段
+    pub thread: bool,
+    /// 输出 `metadata` 字段
+    pub metadata: bool,
+}
+
+impl EntryFields {
+    /// 不包含任何元数据
+    pub const NONE: Self = Self {
+        seq: false,
+        timestamp: false,
+        thread: false,
+        metadata: false,
+    };
+
+    /// 包含所有元数据
+    pub const ALL: Self = Self {
+        seq: true,
+        timestamp: true,
+        thread: true,
+        metadata: true,
+    };
+
+    fn is_none(&self) -> bool {
+        *self == Self::NONE
+    }
+}
+
+/// 按EntryFields序列化的条目
+pub(crate) struct EntryJson<'a, V> {
+    pub(crate) entry: &'a StackEntry<V>,
+    pub(crate) fields: EntryFields,
+}
+
+impl<V: Serialize> Serialize for EntryJson<'_, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (entry, fields) = (self.entry, self.fields);
+        if fields.is_none() {
+            return entry.value.serialize(serializer);
+        }
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("value", &entry.value)?;
+        if fields.seq {
+            map.serialize_entry("seq", &entry.seq)?;
+        }
+        if fields.timestamp {
+            let millis = entry
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64);
+            map.serialize_entry("timestamp", &millis)?;
+        }
+        if fields.thread {
+            map.serialize_entry("thread_id", &entry.thread_id)?;
+            map.serialize_entry("thread_name", &entry.thread_name)?;
+        }
+        if fields.metadata {
+            map.serialize_entry("metadata", &entry.metadata)?;
+        }
+        map.end()
+    }
+}
+
+/// 按EntryFields序列化的一个栈
+pub(crate) struct StackJson<'a, V> {
+    pub(crate) entries: &'a [StackEntry<V>],
+    pub(crate) fields: EntryFields,
+}
+
+impl<V: Serialize> Serialize for StackJson<'_, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.entries.iter().map(|entry| EntryJson {
+            entry,
+            fields: self.fields,
+        }))
+    }
+}
@@ -0,0 +1,198 @@
+// src/event.rs
+//! 栈修改通知
+
+use crate::KeyMatch;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, TrySendError};
+use std::sync::{Arc, RwLock};
+
+/// 栈修改事件
+///
+/// 回调监听器收到的是借用键值的 `Event<&K, &V>`，可通过 `cloned` 转为独立的事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<K, V> {
+    /// 压入了一个值
+    Pushed { key: K, value: V },
+    /// 从栈顶移除了count个值，包括 `pop`、`pop_n`、`truncate` 和 `clear`
+    Popped { key: K, count: usize },
+    /// 整个栈被删除，包括 `del_stack`、内存预算淘汰和整栈过期
+    Deleted { key: K },
+    /// count个值因过期被移除；整个栈过期时count为栈中剩余值的个数，随后还有一个 `Deleted` 事件
+    Expired { key: K, count: usize },
+}
+
+impl<K, V> Event<K, V> {
+    /// 事件对应的键
+    pub fn key(&self) -> &K {
+        match self {
+            Event::Pushed { key, .. }
+            | Event::Popped { key, .. }
+            | Event::Deleted { key }
+            | Event::Expired { key, .. } => key,
+        }
+    }
+}
+
+impl<K: Clone, V: Clone> Event<&K, &V> {
+    /// 克隆键和值，生成独立的事件
+    pub fn cloned(&self) -> Event<K, V> {
+        match *self {
+            Event::Pushed { key, value } => Event::Pushed {
+                key: key.clone(),
+                value: value.clone(),
+            },
+            Event::Popped { key, count } => Event::Popped {
+                key: key.clone(),
+                count,
+            },
+            Event::Deleted { key } => Event::Deleted { key: key.clone() },
+            Event::Expired { key, count } => Event::Expired {
+                key: key.clone(),
+                count,
+            },
+        }
+    }
+}
+
+/// 监听器编号，用于移除监听器
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ListenerId(u64);
+
+// 返回false表示监听器已失效，需要移除
+type Listener<K, V> = Box<dyn Fn(&Event<&K, &V>) -> bool + Send + Sync>;
+
+/// 已注册的监听器
+pub(crate) struct Listeners<K, V> {
+    list: RwLock<Vec<(ListenerId, Listener<K, V>)>>,
+    next_id: AtomicU64,
+}
+
+impl<K, V> Default for Listeners<K, V> {
+    fn default() -> Self {
+        Self {
+            list: RwLock::new(Vec::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<K, V> Listeners<K, V> {
+    /// 注册监听器
+    pub(crate) fn add(
+        &self,
+        listener: impl Fn(&Event<&K, &V>) -> bool + Send + Sync + 'static,
+    ) -> ListenerId {
+        let id = ListenerId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.list
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push((id, Box::new(listener)));
+        id
+    }
+
+    /// 移除监听器
+    pub(crate) fn remove(&self, id: ListenerId) -> bool {
+        let mut list = self
+            .list
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let len = list.len();
+        list.retain(|(listener_id, _)| *listener_id != id);
+        list.len() != len
+    }
+
+    /// 已注册的监听器个数
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.list
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .len()
+    }
+
+    /// 有监听器时生成事件并通知所有监听器
+    pub(crate) fn emit<'a>(&self, event: impl FnOnce() -> Event<&'a K, &'a V>)
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let mut dead = Vec::new();
+        {
+            let list = self
+                .list
+                .read()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if list.is_empty() {
+                return;
+            }
+            let event = event();
+            for (id, listener) in list.iter() {
+                if !listener(&event) {
+                    dead.push(*id);
+                }
+            }
+        }
+        if !dead.is_empty() {
+            self.list
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .retain(|(id, _)| !dead.contains(id));
+        }
+    }
+}
+
+/// 通过 `subscribe` 订阅的事件流
+///
+/// 可直接当作 `Receiver<Event<K, V>>` 使用；被丢弃后订阅在下一个事件时自动取消
+pub struct Subscription<K, V> {
+    receiver: Receiver<Event<K, V>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl<K, V> Subscription<K, V> {
+    /// 因通道已满而丢弃的事件个数
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<K, V> Deref for Subscription<K, V> {
+    type Target = Receiver<Event<K, V>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.receiver
+    }
+}
+
+/// 创建订阅及对应的监听器
+pub(crate) fn subscription<K, V>(
+    key_filter: impl KeyMatch + Send + Sync + 'static,
+    capacity: usize,
+) -> (
+    Subscription<K, V>,
+    impl Fn(&Event<&K, &V>) -> bool + Send + Sync + 'static,
+)
+where
+    K: AsRef<str> + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    // 容量为0的同步通道是会合通道，try_send总是失败
+    let (sender, receiver) = mpsc::sync_channel(capacity.max(1));
+    let dropped = Arc::new(AtomicU64::new(0));
+    let counter = Arc::clone(&dropped);
+    let listener = move |event: &Event<&K, &V>| {
+        if !key_filter.is_match(event.key().as_ref()) {
+            return true;
+        }
+        match sender.try_send(event.cloned()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                counter.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    };
+    (Subscription { receiver, dropped }, listener)
+}
@@ -0,0 +1,182 @@
+// src/filter.rs
+//! 键名过滤
+
+/// 键名匹配规则
+///
+/// 除 `KeyFilter` 外，`&str` 按子字符串匹配，`Fn(&str) -> bool` 闭包按返回值匹配
+pub trait KeyMatch {
+    /// 键名是否匹配
+    fn is_match(&self, key: &str) -> bool;
+}
+
+/// 常用的键名过滤条件
+#[derive(Debug, Clone)]
+pub enum KeyFilter {
+    /// 匹配所有键
+    All,
+    /// 键名完全相等
+    Exact(String),
+    /// 键名以指定字符串开头
+    Prefix(String),
+    /// 键名以指定字符串结尾
+    Suffix(String),
+    /// 键名包含指定字符串
+    Contains(String),
+    /// 通配符匹配：`*` 匹配任意个字符，`?` 匹配单个字符，如 `server:*:err`
+    Glob(String),
+    /// 正则表达式匹配（需要启用 `regex` feature）
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl KeyFilter {
+    /// 键名完全相等
+    pub fn exact(s: impl Into<String>) -> Self {
+        Self::Exact(s.into())
+    }
+
+    /// 键名以指定字符串开头
+    pub fn prefix(s: impl Into<String>) -> Self {
+        Self::Prefix(s.into())
+    }
+
+    /// 键名以指定字符串结尾
+    pub fn suffix(s: impl Into<String>) -> Self {
+        Self::Suffix(s.into())
+    }
+
+    /// 键名包含指定字符串
+    pub fn contains(s: impl Into<String>) -> Self {
+        Self::Contains(s.into())
+    }
+
+    /// 通配符匹配
+    pub fn glob(pattern: impl Into<String>) -> Self {
+        Self::Glob(pattern.into())
+    }
+
+    /// 正则表达式匹配
+    ///
+    /// # 返回值
+    /// 表达式无效时返回错误
+    #[cfg(feature = "regex")]
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        regex::Regex::new(pattern).map(Self::Regex)
+    }
+}
+
+impl KeyMatch for KeyFilter {
+    fn is_match(&self, key: &str) -> bool {
+        match self {
+            KeyFilter::All => true,
+            KeyFilter::Exact(s) => key == s,
+            KeyFilter::Prefix(s) => key.starts_with(s.as_str()),
+            KeyFilter::Suffix(s) => key.ends_with(s.as_str()),
+            KeyFilter::Contains(s) => key.contains(s.as_str()),
+            KeyFilter::Glob(pattern) => glob_match(pattern, key),
+            #[cfg(feature = "regex")]
+            KeyFilter::Regex(re) => re.is_match(key),
+        }
+    }
+}
+
+impl KeyMatch for &KeyFilter {
+    fn is_match(&self, key: &str) -> bool {
+        (**self).is_match(key)
+    }
+}
+
+impl KeyMatch for &str {
+    fn is_match(&self, key: &str) -> bool {
+        key.contains(*self)
+    }
+}
+
+impl KeyMatch for String {
+    fn is_match(&self, key: &str) -> bool {
+        key.contains(self.as_str())
+    }
+}
+
+impl KeyMatch for &String {
+    fn is_match(&self, key: &str) -> bool {
+        key.contains(self.as_str())
+    }
+}
+
+impl<F: Fn(&str) -> bool> KeyMatch for F {
+    fn is_match(&self, key: &str) -> bool {
+        self(key)
+    }
+}
+
+/// 通配符匹配，`*` 匹配任意个字符，`?` 匹配单个字符
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // 最近一个`*`的位置，及其当前匹配到的文本位置
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // 回溯：让上一个`*`多匹配一个字符
+                Some((sp, st)) => {
+                    p = sp + 1;
+                    t = st + 1;
+                    star = Some((sp, st + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("server:*:err", "server:1:err"));
+        assert!(glob_match("server:*:err", "server::err"));
+        assert!(glob_match("server:*:err", "server:a:b:err"));
+        assert!(!glob_match("server:*:err", "myserver:1:err"));
+        assert!(!glob_match("server:*:err", "server:1:errs"));
+        assert!(glob_match("s?rver*", "server"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("?", ""));
+        assert!(glob_match("*a*b", "xaxxb"));
+    }
+
+    #[test]
+    fn test_key_filter() {
+        assert!(KeyFilter::All.is_match("x"));
+        assert!(KeyFilter::exact("a").is_match("a"));
+        assert!(!KeyFilter::exact("a").is_match("ab"));
+        assert!(KeyFilter::prefix("server:").is_match("server:1"));
+        assert!(!KeyFilter::prefix("server:").is_match("myserver:1"));
+        assert!(KeyFilter::suffix(":err").is_match("x:err"));
+        assert!(KeyFilter::contains("erv").is_match("server"));
+        assert!(KeyMatch::is_match(&"erv", "server"));
+        assert!((|k: &str| k.len() == 3).is_match("abc"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex_filter() {
+        let filter = KeyFilter::regex(r"^server:\d+$").unwrap();
+        assert!(filter.is_match("server:12"));
+        assert!(!filter.is_match("server:x"));
+        assert!(KeyFilter::regex("(").is_err());
+    }
+}
@@ -0,0 +1,322 @@
+// src/flame.rs
+//! 折叠栈格式及火焰图导出
+
+use crate::frame::Frame;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+
+// 火焰图中每层的高度、字体宽度及边距，单位为像素
+const FRAME_HEIGHT: f64 = 16.0;
+const CHAR_WIDTH: f64 = 7.0;
+const PAD_X: f64 = 10.0;
+const PAD_TOP: f64 = 34.0;
+const PAD_BOTTOM: f64 = 10.0;
+
+/// 导出折叠栈时如何划分样本
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SampleMode {
+    /// 每个键的整个栈是一个样本，每个值是一帧，栈底是根
+    #[default]
+    Key,
+    /// 每个值是一段完整的JS调用栈文本，解析为帧后作为一个样本，最外层的帧是根；
+    /// 帧名取函数名，匿名函数取位置，无法解析出帧的值被跳过
+    Value,
+}
+
+/// 聚合后的折叠栈：相同的调用栈合并计数
+///
+/// `Display` 输出Brendan Gregg的折叠格式，每行 `根;...;叶 次数`，按调用栈排序，
+/// 可直接交给 `flamegraph.pl`、inferno、speedscope 等工具
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FoldedStacks {
+    counts: BTreeMap<Vec<String>, u64>,
+}
+
+impl FoldedStacks {
+    /// 创建空的折叠栈
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 添加一个调用栈的样本
+    ///
+    /// # 参数
+    /// - frames: 从根到叶的帧，帧名中的 `;` 替换为 `:`，换行替换为空格
+    /// - count: 样本个数
+    ///
+    /// # 注意
+    /// 没有帧的调用栈被忽略
+    pub fn add<S: AsRef<str>>(&mut self, frames: impl IntoIterator<Item = S>, count: u64) {
+        let frames: Vec<String> = frames
+            .into_iter()
+            .map(|frame| frame.as_ref().replace(';', ":").replace(['\r', '\n'], " "))
+            .collect();
+        if !frames.is_empty() && count > 0 {
+            *self.counts.entry(frames).or_insert(0) += count;
+        }
+    }
+
+    /// 遍历(从根到叶的帧, 样本个数)
+    pub fn iter(&self) -> impl Iterator<Item = (&[String], u64)> {
+        self.counts
+            .iter()
+            .map(|(frames, &count)| (frames.as_slice(), count))
+    }
+
+    /// 不同调用栈的个数
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// 是否没有任何样本
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// 样本总数
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// 以SVG火焰图写出，根在底部，宽度与样本数成正比
+    ///
+    /// # 注意
+    /// 同一层中同名的帧按调用栈合并，按名称排序；每个矩形的提示中包含样本数和占比
+    pub fn write_svg(&self, mut writer: impl Write, options: &FlameGraphOptions) -> io::Result<()> {
+        let mut root = Node::default();
+        for (frames, count) in self.iter() {
+            root.add(frames, count);
+        }
+        let depth = root.depth();
+        let width = f64::from(options.width.max(100));
+        let height = PAD_TOP + depth as f64 * FRAME_HEIGHT + PAD_BOTTOM;
+        writeln!(
+            writer,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="Verdana, sans-serif">"#
+        )?;
+        writeln!(
+            writer,
+            r##"<rect width="100%" height="100%" fill="#f8f8f8"/>"##
+        )?;
+        writeln!(
+            writer,
+            r#"<text x="{}" y="22" font-size="16" text-anchor="middle">{}</text>"#,
+            width / 2.0,
+            Escape(&options.title)
+        )?;
+        let mut render = Render {
+            writer: &mut writer,
+            total: root.count,
+            scale: if root.count == 0 {
+                0.0
+            } else {
+                (width - 2.0 * PAD_X) / root.count as f64
+            },
+            bottom: height - PAD_BOTTOM,
+            min_width: options.min_width,
+        };
+        render.children(&root, 0, PAD_X)?;
+        writeln!(writer, "</svg>")
+    }
+}
+
+impl fmt::Display for FoldedStacks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (frames, count) in self.iter() {
+            writeln!(f, "{} {count}", frames.join(";"))?;
+        }
+        Ok(())
+    }
+}
+
+/// 火焰图的可选参数
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlameGraphOptions {
+    /// 标题
+    pub title: String,
+    /// 图片宽度，单位为像素，至少为100
+    pub width: u32,
+    /// 宽度小于该值的帧不绘制，单位为像素
+    pub min_width: f64,
+}
+
+impl Default for FlameGraphOptions {
+    fn default() -> Self {
+        Self {
+            title: "Flame Graph".to_string(),
+            width: 1200,
+            min_width: 0.1,
+        }
+    }
+}
+
+/// 按JS调用栈文本生成从根到叶的帧名
+pub(crate) fn js_frames(text: &str) -> Vec<String> {
+    let mut frames: Vec<String> = Frame::parse_stack(text)
+        .into_iter()
+        .map(|frame| match frame.function {
+            Some(function) => function,
+            None => frame.to_string(),
+        })
+        .collect();
+    // 调用栈文本中最内层的帧在前
+    frames.reverse();
+    frames
+}
+
+/// 按帧名合并的调用树
+#[derive(Default)]
+struct Node<'a> {
+    count: u64,
+    children: BTreeMap<&'a str, Node<'a>>,
+}
+
+impl<'a> Node<'a> {
+    fn add(&mut self, frames: &'a [String], count: u64) {
+        self.count += count;
+        if let Some((first, rest)) = frames.split_first() {
+            self.children.entry(first).or_default().add(rest, count);
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.children
+            .values()
+            .map(|child| child.depth() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+struct Render<'w, W> {
+    writer: &'w mut W,
+    total: u64,
+    // 每个样本的宽度
+    scale: f64,
+    // 根所在层的底边
+    bottom: f64,
+    min_width: f64,
+}
+
+impl<W: Write> Render<'_, W> {
+    /// 从x开始绘制node的所有子节点，depth为子节点所在的层
+    fn children(&mut self, node: &Node<'_>, depth: usize, mut x: f64) -> io::Result<()> {
+        for (name, child) in &node.children {
+            let width = child.count as f64 * self.scale;
+            if width >= self.min_width {
+                self.frame(name, child.count, depth, x, width)?;
+                self.children(child, depth + 1, x)?;
+            }
+            x += width;
+        }
+        Ok(())
+    }
+
+    fn frame(
+        &mut self,
+        name: &str,
+        count: u64,
+        depth: usize,
+        x: f64,
+        width: f64,
+    ) -> io::Result<()> {
+        let y = self.bottom - (depth + 1) as f64 * FRAME_HEIGHT;
+        let percent = count as f64 * 100.0 / self.total as f64;
+        let (r, g, b) = color(name);
+        writeln!(
+            self.writer,
+            r#"<g><title>{} ({count} samples, {percent:.2}%)</title><rect x="{x:.2}" y="{y}" width="{width:.2}" height="{}" fill="rgb({r},{g},{b})" rx="2"/>"#,
+            Escape(name),
+            FRAME_HEIGHT - 1.0,
+        )?;
+        // 放不下的帧名截断并以..结尾，太窄时不显示
+        let fit = ((width - 6.0) / CHAR_WIDTH).max(0.0) as usize;
+        if fit >= 3 {
+            let label: String = if name.chars().count() > fit {
+                name.chars().take(fit - 2).chain("..".chars()).collect()
+            } else {
+                name.to_string()
+            };
+            writeln!(
+                self.writer,
+                r#"<text x="{:.2}" y="{}" font-size="12">{}</text>"#,
+                x + 3.0,
+                y + FRAME_HEIGHT - 4.0,
+                Escape(&label)
+            )?;
+        }
+        writeln!(self.writer, "</g>")
+    }
+}
+
+/// 按帧名生成稳定的暖色
+fn color(name: &str) -> (u8, u8, u8) {
+    // FNV-1a
+    let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    });
+    let r = 205 + (hash % 50) as u8;
+    let g = ((hash >> 8) % 230) as u8;
+    let b = ((hash >> 16) % 55) as u8;
+    (r, g, b)
+}
+
+/// 转义XML特殊字符
+struct Escape<'a>(&'a str);
+
+impl fmt::Display for Escape<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                c => write!(f, "{c}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_folded() {
+        let mut folded = FoldedStacks::new();
+        folded.add(["main", "render"], 1);
+        folded.add(["main", "a;b"], 2);
+        folded.add(["main", "render"], 1);
+        folded.add(Vec::<String>::new(), 1);
+        assert_eq!(folded.to_string(), "main;a:b 2\nmain;render 2\n");
+        assert_eq!((folded.len(), folded.total()), (2, 4));
+    }
+
+    #[test]
+    fn test_js_frames() {
+        assert_eq!(
+            js_frames("Error\n    at render (a.js:1:2)\n    at a.js:9:1\n    at main (a.js:3:4)"),
+            ["main", "a.js:9:1", "render"]
+        );
+    }
+
+    #[test]
+    fn test_svg() {
+        let mut folded = FoldedStacks::new();
+        folded.add(["main", "render<T>"], 3);
+        folded.add(["main", "x"], 1);
+        let mut out = Vec::new();
+        folded
+            .write_svg(&mut out, &FlameGraphOptions::default())
+            .unwrap();
+        let svg = String::from_utf8(out).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<rect x=").count(), 3);
+        assert!(svg.contains("<title>main (4 samples, 100.00%)</title>"));
+        assert!(svg.contains("<title>render&lt;T&gt; (3 samples, 75.00%)</title>"));
+    }
+}
@@ -0,0 +1,217 @@
+// src/format.rs
+//! 栈内容的输出格式
+
+use crate::rle::ValuesJson;
+use crate::stack::Stack;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{self, Write};
+
+/// `get_as`/`iter_as` 等接口的输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    /// JSON数组，与 `get`/`iter` 相同：单个栈为 `[值, ...]`，多个栈为 `[[键名, [值, ...]], ...]`
+    #[default]
+    Json,
+    /// 缩进两个空格的 `Json`
+    PrettyJson,
+    /// JSON对象 `{"键名": [值, ...], ...}`，键必须序列化为字符串或数字
+    Object,
+    /// 每个栈一行 `{"key": 键名, "values": [值, ...]}`，每行以换行符结尾
+    Ndjson,
+    /// 每个值一行 `{"key": 键名, "index": 栈中位置, "value": 值}`，位置从栈底的0开始
+    NdjsonEntries,
+    /// 便于终端阅读的文本：每个栈先输出键名，再从栈顶到栈底逐行输出缩进的值
+    ///
+    /// 字符串原样输出，其它值输出为JSON
+    Text,
+}
+
+/// 按格式写出单个栈；`Json`/`PrettyJson` 只写出栈内容，其它格式同时写出键名
+pub(crate) fn write_one<K, V>(
+    mut writer: impl Write,
+    format: OutputFormat,
+    key: &K,
+    stack: &Stack<V>,
+) -> io::Result<()>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    match format {
+        OutputFormat::Json => Ok(serde_json::to_writer(writer, &ValuesJson(stack))?),
+        OutputFormat::PrettyJson => Ok(serde_json::to_writer_pretty(writer, &ValuesJson(stack))?),
+        _ => {
+            let mut stacks = StackWriter::new(&mut writer, format)?;
+            stacks.write(key, stack)?;
+            stacks.finish().map(|_| ())
+        }
+    }
+}
+
+/// 按格式逐个写出多个栈
+pub(crate) struct StackWriter<W> {
+    writer: W,
+    format: OutputFormat,
+    written: usize,
+}
+
+impl<W: Write> StackWriter<W> {
+    /// 写出开头的括号
+    pub(crate) fn new(mut writer: W, format: OutputFormat) -> io::Result<Self> {
+        match format {
+            OutputFormat::Json | OutputFormat::PrettyJson => writer.write_all(b"[")?,
+            OutputFormat::Object => writer.write_all(b"{")?,
+            _ => {}
+        }
+        Ok(Self {
+            writer,
+            format,
+            written: 0,
+        })
+    }
+
+    /// 写出一个栈
+    pub(crate) fn write<K, V>(&mut self, key: &K, stack: &Stack<V>) -> io::Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        let first = self.written == 0;
+        self.written += 1;
+        let writer = &mut self.writer;
+        match self.format {
+            OutputFormat::Json => {
+                if !first {
+                    writer.write_all(b",")?;
+                }
+                serde_json::to_writer(writer, &(key, ValuesJson(stack)))?;
+            }
+            OutputFormat::PrettyJson => {
+                writer.write_all(if first { b"\n  " } else { b",\n  " })?;
+                serde_json::to_writer_pretty(Indent(writer, b"  "), &(key, ValuesJson(stack)))?;
+            }
+            OutputFormat::Object => {
+                if !first {
+                    writer.write_all(b",")?;
+                }
+                serde_json::to_writer(&mut *writer, &object_key(key)?)?;
+                writer.write_all(b":")?;
+                serde_json::to_writer(writer, &ValuesJson(stack))?;
+            }
+            OutputFormat::Ndjson => {
+                serde_json::to_writer(
+                    &mut *writer,
+                    &KeyRecord {
+                        key,
+                        values: ValuesJson(stack),
+                    },
+                )?;
+                writer.write_all(b"\n")?;
+            }
+            OutputFormat::NdjsonEntries => {
+                for (index, value) in stack.iter().enumerate() {
+                    serde_json::to_writer(&mut *writer, &EntryRecord { key, index, value })?;
+                    writer.write_all(b"\n")?;
+                }
+            }
+            OutputFormat::Text => {
+                writeln!(writer, "{}", text(key)?)?;
+                let values: Vec<&V> = stack.iter().collect();
+                for value in values.into_iter().rev() {
+                    writer.write_all(b"    ")?;
+                    Indent(&mut *writer, b"    ").write_all(text(value)?.as_bytes())?;
+                    writer.write_all(b"\n")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 写出结尾的括号
+    ///
+    /// # 返回值
+    /// 写出的栈个数
+    pub(crate) fn finish(mut self) -> io::Result<usize> {
+        match self.format {
+            OutputFormat::Json => self.writer.write_all(b"]")?,
+            OutputFormat::PrettyJson if self.written > 0 => self.writer.write_all(b"\n]")?,
+            OutputFormat::PrettyJson => self.writer.write_all(b"]")?,
+            OutputFormat::Object => self.writer.write_all(b"}")?,
+            _ => {}
+        }
+        Ok(self.written)
+    }
+}
+
+#[derive(Serialize)]
+struct KeyRecord<'a, K, V> {
+    key: &'a K,
+    values: ValuesJson<'a, V>,
+}
+
+#[derive(Serialize)]
+struct EntryRecord<'a, K, V> {
+    key: &'a K,
+    index: usize,
+    value: &'a V,
+}
+
+/// 在每个换行符后插入缩进
+///
+/// JSON字符串中的换行符都会被转义，因此只会缩进格式化时插入的换行
+struct Indent<W>(W, &'static [u8]);
+
+impl<W: Write> Write for Indent<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        for (i, line) in buf.split(|&b| b == b'\n').enumerate() {
+            if i > 0 {
+                self.0.write_all(b"\n")?;
+                self.0.write_all(self.1)?;
+            }
+            self.0.write_all(line)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// JSON对象的键：字符串原样使用，数字和布尔值转为字符串
+fn object_key<K: Serialize>(key: &K) -> io::Result<String> {
+    match serde_json::to_value(key)? {
+        Value::String(s) => Ok(s),
+        value @ (Value::Number(_) | Value::Bool(_)) => Ok(value.to_string()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "key must be a string or number",
+        )),
+    }
+}
+
+/// 文本格式：字符串原样输出，其它值输出为JSON
+fn text<T: Serialize>(value: &T) -> io::Result<String> {
+    match serde_json::to_value(value)? {
+        Value::String(s) => Ok(s),
+        value => Ok(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indent() {
+        let mut out = Vec::new();
+        Indent(&mut out, b"  ").write_all(b"[\n  1\n]").unwrap();
+        assert_eq!(out, b"[\n    1\n  ]");
+    }
+}
@@ -0,0 +1,236 @@
+// src/frame.rs
+//! 解析JS引擎的调用栈文本
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// 调用栈中的一帧
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Frame {
+    /// 函数名，匿名函数为None
+    pub function: Option<String>,
+    /// 文件名或URL，也可能是 `native`、`<anonymous>` 等引擎给出的标记
+    pub file: Option<String>,
+    /// 行号，从1开始
+    pub line: Option<u32>,
+    /// 列号，从1开始
+    pub column: Option<u32>,
+}
+
+impl Frame {
+    /// 解析一行调用栈文本
+    ///
+    /// 支持以下格式：
+    /// - V8/QuickJS: `at fn (file:line:col)`、`at file:line:col`、`at fn (native)`，位置须像文件名并带有行号，QuickJS可能没有列号
+    /// - SpiderMonkey/JavaScriptCore: `fn@file:line:col`、`@file:line:col`，位置须像文件名或URL并带有行号和列号
+    ///
+    /// # 返回值
+    /// 不是调用栈帧（如第一行的错误信息）时返回None
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("at ") {
+            return parse_v8(rest.trim());
+        }
+        let (function, location) = line.split_once('@')?;
+        let frame = parse_location(location);
+        // 错误信息中也可能有@（如 `x@host:8080`），要求像文件的位置及行号和列号
+        let (Some(file), Some(_), Some(_)) = (&frame.file, frame.line, frame.column) else {
+            return None;
+        };
+        if !is_file_like(file) && !is_marker(file) {
+            return None;
+        }
+        Some(Self {
+            function: non_empty(function),
+            ..frame
+        })
+    }
+
+    /// 解析整段调用栈文本，跳过不是调用栈帧的行
+    pub fn parse_stack(text: &str) -> Vec<Self> {
+        text.lines().filter_map(Self::parse).collect()
+    }
+}
+
+impl fmt::Display for Frame {
+    /// 按V8的格式输出：`fn (file:line:col)`，匿名函数只输出位置
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(file) = &self.file else {
+            return f.write_str(self.function.as_deref().unwrap_or("<anonymous>"));
+        };
+        if let Some(function) = &self.function {
+            write!(f, "{function} (")?;
+        }
+        f.write_str(file)?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+        }
+        if let Some(column) = self.column {
+            write!(f, ":{column}")?;
+        }
+        if self.function.is_some() {
+            f.write_str(")")?;
+        }
+        Ok(())
+    }
+}
+
+/// 解析V8格式中 `at ` 之后的部分
+///
+/// 错误信息中也可能有以 `at ` 开头的行（如 `at least one item required`），
+/// 要求位置是 `native`、`<anonymous>` 之类的标记，或像文件并带有行号，否则返回None
+fn parse_v8(rest: &str) -> Option<Frame> {
+    let Some(open) = rest.strip_suffix(')').and_then(matching_paren) else {
+        // 没有函数名时直接是位置
+        let frame = parse_location(rest);
+        return is_v8_location(&frame).then_some(frame);
+    };
+    let function = rest[..open].trim();
+    let mut location = &rest[open + 1..rest.len() - 1];
+    // eval中的帧形如 `eval at fn (file:1:2), <anonymous>:3:4`，取最后的实际位置
+    if let Some((_, last)) = location.rsplit_once(", ") {
+        location = last;
+    }
+    let frame = parse_location(location);
+    if !is_v8_location(&frame) {
+        return None;
+    }
+    Some(Frame {
+        function: non_empty(function.strip_prefix("async ").unwrap_or(function)),
+        ..frame
+    })
+}
+
+/// V8/QuickJS帧的位置是否有效，QuickJS可能没有列号
+fn is_v8_location(frame: &Frame) -> bool {
+    match &frame.file {
+        Some(file) if is_marker(file) => true,
+        Some(file) => frame.line.is_some() && is_file_like(file),
+        None => false,
+    }
+}
+
+/// 与末尾的右括号匹配的左括号位置，text不含该右括号
+fn matching_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' if depth == 0 => return Some(i),
+            '(' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// 解析 `file:line:col`、`file:line` 或 `file`
+fn parse_location(location: &str) -> Frame {
+    let location = location.trim();
+    let mut file = location;
+    let mut numbers = Vec::with_capacity(2);
+    while numbers.len() < 2 {
+        match file.rsplit_once(':') {
+            Some((rest, n)) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => {
+                let Ok(n) = n.parse() else { break };
+                numbers.push(n);
+                file = rest;
+            }
+            _ => break,
+        }
+    }
+    numbers.reverse();
+    Frame {
+        function: None,
+        file: non_empty(file),
+        line: numbers.first().copied(),
+        column: numbers.get(1).copied(),
+    }
+}
+
+/// 是否像文件名或URL：含有路径分隔符或扩展名
+fn is_file_like(file: &str) -> bool {
+    file.contains(['/', '\\', '.'])
+}
+
+/// 是否是引擎给出的位置标记，如 `native`、`<anonymous>`
+fn is_marker(file: &str) -> bool {
+    file == "native" || (file.starts_with('<') && file.ends_with('>'))
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    let s = s.trim();
+    (!s.is_empty()).then(|| s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(function: Option<&str>, file: &str, line: Option<u32>, column: Option<u32>) -> Frame {
+        Frame {
+            function: function.map(str::to_string),
+            file: Some(file.to_string()),
+            line,
+            column,
+        }
+    }
+
+    #[test]
+    fn test_parse_v8() {
+        let text = "TypeError: x is undefined\n    at render (http://a.com/app.js:10:5)\n    \
+                    at http://a.com/app.js:20:1\n    at new Foo (app.js:3:7)\n    \
+                    at async load (app.js:4:2)\n    at Array.map (native)\n    \
+                    at eval (eval at run (app.js:1:2), <anonymous>:5:6)\n    \
+                    at least one item required\n    at position 5\n    at step (retry later)";
+        assert_eq!(
+            Frame::parse_stack(text),
+            vec![
+                frame(Some("render"), "http://a.com/app.js", Some(10), Some(5)),
+                frame(None, "http://a.com/app.js", Some(20), Some(1)),
+                frame(Some("new Foo"), "app.js", Some(3), Some(7)),
+                frame(Some("load"), "app.js", Some(4), Some(2)),
+                frame(Some("Array.map"), "native", None, None),
+                frame(Some("eval"), "<anonymous>", Some(5), Some(6)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_spidermonkey() {
+        let text = "render@http://a.com/app.js:10:5\n@app.js:20:1\nFoo/<@app.js:3:7\n\
+                    Error: mail user@example.com\nx@host:8080\nx@host:80:1\n@<anonymous>:1:2";
+        assert_eq!(
+            Frame::parse_stack(text),
+            vec![
+                frame(Some("render"), "http://a.com/app.js", Some(10), Some(5)),
+                frame(None, "app.js", Some(20), Some(1)),
+                frame(Some("Foo/<"), "app.js", Some(3), Some(7)),
+                frame(None, "<anonymous>", Some(1), Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_quickjs() {
+        let text = "    at render (app.js:10)\n    at <eval> (app.js:1)\n    at forEach (native)";
+        assert_eq!(
+            Frame::parse_stack(text),
+            vec![
+                frame(Some("render"), "app.js", Some(10), None),
+                frame(Some("<eval>"), "app.js", Some(1), None),
+                frame(Some("forEach"), "native", None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let f = frame(Some("render"), "app.js", Some(10), Some(5));
+        assert_eq!(f.to_string(), "render (app.js:10:5)");
+        assert_eq!(Frame::parse(&format!("at {f}")), Some(f));
+        let f = frame(None, "app.js", Some(3), None);
+        assert_eq!(f.to_string(), "app.js:3");
+        assert_eq!(Frame::parse(&format!("at {f}")), Some(f));
+    }
+}
@@ -0,0 +1,124 @@
+// src/intern.rs
+//! 字符串驻留
+
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// 新增多少个字符串后自动清理一次未使用的字符串，至少为该值
+const MIN_SWEEP_INTERVAL: usize = 1024;
+
+/// 字符串驻留统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InternStats {
+    /// 驻留的不同字符串个数
+    pub unique: usize,
+    /// 驻留字符串的总字节数
+    pub bytes: usize,
+    /// 存储中对驻留字符串的引用个数
+    pub references: usize,
+    /// 相比每个引用单独分配字符串节省的字节数
+    pub bytes_saved: usize,
+}
+
+/// 字符串驻留表，相同的字符串只保存一份
+///
+/// 表本身持有每个字符串的一个引用，引用计数为1说明已没有栈在使用，清理时释放
+#[derive(Default)]
+pub(crate) struct Interner {
+    strings: DashMap<Arc<str>, ()>,
+    // 上次清理后新增的字符串个数
+    inserted: AtomicUsize,
+    // 上次清理后剩余的字符串个数
+    live: AtomicUsize,
+}
+
+impl Interner {
+    /// 获取与s相同的驻留字符串，不存在时驻留s本身
+    pub(crate) fn intern(&self, s: Arc<str>) -> Arc<str> {
+        if let Some(interned) = self.strings.get(&s) {
+            return Arc::clone(interned.key());
+        }
+        match self.strings.entry(s) {
+            Entry::Occupied(entry) => Arc::clone(entry.key()),
+            Entry::Vacant(entry) => {
+                let interned = Arc::clone(entry.key());
+                entry.insert(());
+                self.inserted.fetch_add(1, Ordering::Relaxed);
+                interned
+            }
+        }
+    }
+
+    /// 新增的字符串与上次剩余的一样多时清理一次，清理开销均摊到每次新增
+    ///
+    /// # 注意
+    /// 清理需要遍历整个驻留表，应在释放栈锁之后调用；多个线程同时调用时只有一个清理
+    pub(crate) fn sweep_if_due(&self) {
+        let inserted = self.inserted.load(Ordering::Relaxed);
+        if inserted >= MIN_SWEEP_INTERVAL.max(self.live.load(Ordering::Relaxed))
+            && self
+                .inserted
+                .compare_exchange(inserted, 0, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.sweep();
+        }
+    }
+
+    /// 获取与s相同的驻留字符串
+    pub(crate) fn intern_str(&self, s: &str) -> Arc<str> {
+        match self.strings.get(s) {
+            Some(interned) => Arc::clone(interned.key()),
+            None => self.intern(Arc::from(s)),
+        }
+    }
+
+    /// 释放没有栈在使用的字符串
+    ///
+    /// # 返回值
+    /// 释放的字符串个数
+    pub(crate) fn sweep(&self) -> usize {
+        let before = self.strings.len();
+        self.strings.retain(|s, _| Arc::strong_count(s) > 1);
+        let live = self.strings.len();
+        self.inserted.store(0, Ordering::Relaxed);
+        self.live.store(live, Ordering::Relaxed);
+        before.saturating_sub(live)
+    }
+
+    /// 统计驻留的字符串
+    pub(crate) fn stats(&self) -> InternStats {
+        let mut stats = InternStats::default();
+        for entry in self.strings.iter() {
+            let s = entry.key();
+            // 减去驻留表自身持有的引用
+            let references = Arc::strong_count(s) - 1;
+            stats.unique += 1;
+            stats.bytes += s.len();
+            stats.references += references;
+            stats.bytes_saved += references.saturating_sub(1) * s.len();
+        }
+        stats
+    }
+}
+
+/// 存储创建键和压入值时的驻留函数
+pub(crate) struct Interning<K, V> {
+    pub(crate) key: fn(&Interner, K) -> K,
+    pub(crate) value: fn(&Interner, V) -> V,
+}
+
+impl<K, V> Clone for Interning<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for Interning<K, V> {}
+
+/// 驻留Arc<str>
+pub(crate) fn intern_arc(interner: &Interner, s: Arc<str>) -> Arc<str> {
+    interner.intern(s)
+}
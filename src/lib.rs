@@ -1,119 +1,295 @@
 // src/lib.rs
+use dashmap::mapref::entry::Entry;
+use dashmap::mapref::one::RefMut;
 use dashmap::DashMap;
-use std::sync::{Arc, Mutex};
+use entry::{EntryMeta, StackJson};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use stack::{into_stack, lock_stack, Stack};
+use std::backtrace::Backtrace;
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::hash::Hash;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+mod backtrace;
+mod budget;
+mod dump;
+mod entry;
+mod event;
+mod filter;
+mod flame;
+mod format;
+mod frame;
+mod intern;
+mod limit;
+mod persist;
+mod rle;
+mod scan;
+mod scope;
+mod sourcemap;
+mod stack;
+mod txn;
+mod wait;
+
+pub use backtrace::BacktraceOptions;
+use budget::{arc_str_size, string_size, EvictionCounters, Sizer};
+pub use budget::{EvictionOrder, EvictionStats, MemoryBudget};
+pub use dump::{install_panic_hook, DumpStats, DumpTarget};
+pub use entry::{EntryFields, EntryOptions, StackEntry};
+use event::Listeners;
+pub use event::{Event, ListenerId, Subscription};
+pub use filter::{KeyFilter, KeyMatch};
+pub use flame::{FlameGraphOptions, FoldedStacks, SampleMode};
+pub use format::OutputFormat;
+use format::StackWriter;
+pub use frame::Frame;
+pub use intern::InternStats;
+use intern::{intern_arc, Interner, Interning};
+use limit::OverflowCounters;
+pub use limit::{CapacityLimit, OverflowPolicy, OverflowStats};
+use persist::{Journal, Record};
+use rle::{CompactJson, Rle, ValuesJson};
+use scan::Candidate;
+pub use scan::{ParseCursorError, ScanCursor, ScanPage};
+pub use scope::ScopeGuard;
+pub use sourcemap::{Resolution, SourceMap, SymbolizedFrame, Symbolizer};
+pub use txn::Transaction;
+pub use wait::PopFuture;
+use wait::Waiters;
 
 #[macro_use]
 extern crate lazy_static;
 
 lazy_static! {
-    // 全局存储js堆栈信息
-    pub static ref GLOBAL_STACK_STORE: Arc<StackStore> = Arc::new(StackStore::new());
+    // 全局存储js堆栈信息，键名和值都驻留，相同的键名和帧字符串只保存一份；
+    // 创建键的接口需要 `&Arc<str>` 键，以字符串切片压入时用 `set_str`
+    pub static ref GLOBAL_STACK_STORE: Arc<StackStore<Arc<str>, Arc<str>>> =
+        Arc::new(StackStore::interned());
+}
+
+/// 栈的访问方式，决定更新哪个逻辑时钟
+#[derive(Clone, Copy)]
+enum Access {
+    /// 只查看元数据，不算作使用
+    Peek,
+    /// 读取栈内容
+    Read,
+    /// 修改栈内容
+    Write,
 }
 
-/// 线程安全的栈式存储结构，使用字符串作为键，支持并发访问
+/// 线程安全的栈式存储结构，支持并发访问
 ///
-/// 使用 DashMap 管理键值对，每个键对应一个受互斥锁(Mutex)保护的字符串栈
-pub struct StackStore {
-    inner: DashMap<String, Mutex<Vec<String>>>,
+/// 使用 DashMap 管理键值对，每个键对应一个受互斥锁(Mutex)保护的栈。
+/// 键类型 `K` 需满足 `Hash + Eq`，值类型 `V` 不做限制；默认键值均为 `String`
+pub struct StackStore<K = String, V = String> {
+    inner: DashMap<K, Mutex<Stack<V>>>,
+    // 按键设置的容量限制，优先于默认限制
+    limits: DashMap<K, CapacityLimit>,
+    default_limit: RwLock<Option<CapacityLimit>>,
+    overflow: OverflowCounters,
+    // 所有键和值的估算字节数
+    memory: AtomicUsize,
+    budget: RwLock<Option<MemoryBudget>>,
+    eviction: EvictionCounters,
+    // 同一时间只允许一个线程执行预算淘汰
+    evicting: Mutex<()>,
+    // 逻辑时钟，用于记录各栈最近读写的先后顺序
+    clock: AtomicU64,
+    // 通过set压入的值默认的存活时间
+    default_ttl: RwLock<Option<Duration>>,
+    sizer: Sizer<K, V>,
+    // 追加写日志，None表示未开启
+    journal: RwLock<Option<Journal<K, V>>>,
+    // 修改通知的监听器
+    listeners: Listeners<K, V>,
+    // 等待值压入的线程和任务
+    waiters: Waiters,
+    // 相邻重复值的游程编码，None表示不合并
+    rle: Option<Rle<V>>,
+    // 字符串驻留，None表示不驻留
+    interning: Option<Interning<K, V>>,
+    interner: Interner,
 }
 
-impl Default for StackStore {
+impl<K, V> Default for StackStore<K, V>
+where
+    K: Hash + Eq,
+{
     fn default() -> Self {
-        Self::new()
+        Self {
+            inner: DashMap::new(),
+            limits: DashMap::new(),
+            default_limit: RwLock::new(None),
+            overflow: OverflowCounters::default(),
+            memory: AtomicUsize::new(0),
+            budget: RwLock::new(None),
+            eviction: EvictionCounters::default(),
+            evicting: Mutex::new(()),
+            clock: AtomicU64::new(0),
+            default_ttl: RwLock::new(None),
+            sizer: Sizer::default(),
+            journal: RwLock::new(None),
+            listeners: Listeners::default(),
+            waiters: Waiters::default(),
+            rle: None,
+            interning: None,
+            interner: Interner::default(),
+        }
     }
 }
 
 impl StackStore {
-    /// 创建新的空StackStore实例
+    /// 创建新的空StackStore实例，键和值均为字符串
+    ///
+    /// 其它键值类型请使用 `StackStore::<K, V>::default()`
     pub fn new() -> Self {
-        Self {
-            inner: DashMap::new(),
-        }
+        Self::default().with_size_fn(string_size, string_size)
+    }
+}
+
+impl StackStore<Arc<str>, Arc<str>> {
+    /// 创建键和值都驻留的字符串存储
+    ///
+    /// 所有栈中相同的键名和值共享同一个 `Arc<str>`，不再被任何栈使用的字符串会被自动释放
+    ///
+    /// # 注意
+    /// - 通过 `set` 等接口压入的任意 `Arc<str>` 都会被换成驻留的字符串
+    /// - 内存统计按每个引用分别计算字符串长度，实际节省的字节数见 `intern_stats`
+    pub fn interned() -> Self {
+        let mut store = Self::default().with_size_fn(arc_str_size, arc_str_size);
+        store.interning = Some(Interning {
+            key: intern_arc,
+            value: intern_arc,
+        });
+        store
+    }
+
+    /// 以字符串切片压入值，键名和值都会驻留
+    ///
+    /// # 返回值
+    /// 同 `set`
+    pub fn set_str(&self, key: &str, value: &str) -> bool {
+        let key = self.interner.intern_str(key);
+        let value = self.interner.intern_str(value);
+        self.set(&key, value)
+    }
+
+    /// 获取字符串驻留统计
+    pub fn intern_stats(&self) -> InternStats {
+        self.interner.stats()
+    }
+
+    /// 立即释放不再被任何栈使用的驻留字符串
+    ///
+    /// # 返回值
+    /// 释放的字符串个数
+    ///
+    /// # 注意
+    /// 驻留新字符串时会按新增数量自动清理，此方法用于大量删除后主动回收
+    pub fn sweep_interned(&self) -> usize {
+        self.interner.sweep()
     }
+}
 
+impl<K, V> StackStore<K, V>
+where
+    K: Hash + Eq,
+{
     /// 将值压入指定键对应的栈顶
     ///
     /// # 参数
     /// - key: 栈的键名
     /// - value: 要压入的值
     ///
+    /// # 返回值
+    /// - true: 值已保存
+    /// - false: 栈已满且容量策略丢弃了新值
+    ///
     /// # 注意
-    /// - 如果键不存在会自动创建空栈
+    /// - 如果键不存在会自动创建空栈，创建与首次压入是原子的
+    /// - 栈已满时按该键的容量限制（见 `set_capacity`）处理
+    /// - 设置了默认存活时间（见 `set_default_ttl`）时，值在到期后自动移除
     /// - 在极少数情况下可能因互斥锁污染导致panic（当持有锁的线程发生panic时）
-    pub fn set(&self, key: &str, value: String) {
-        // 首先尝试获取现有条目
-        if let Some(stack) = self.inner.get(key) {
-            match stack.lock() {
-                Ok(mut guard) => {
-                    guard.push(value);
-                    return;
-                }
-                Err(poisoned) => {
-                    let mut guard = poisoned.into_inner();
-                    guard.push(value);
-                    return;
-                }
-            }
-        }
-
-        // 如果键不存在，创建新的条目
-        let vec = vec![value];
-        self.inner.insert(key.to_string(), Mutex::new(vec));
+    pub fn set<Q>(&self, key: &Q, value: V) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.push_entry(key, value, self.default_ttl(), BTreeMap::new())
     }
 
-    /// 获取指定键对应的整个栈的JSON序列化字符串
+    /// 将带存活时间的值压入指定键对应的栈顶
     ///
     /// # 参数
     /// - key: 栈的键名
+    /// - value: 要压入的值
+    /// - ttl: 值的存活时间，到期后在下一次访问该键或调用 `purge_expired` 时移除
     ///
     /// # 返回值
-    /// - Some(String): 包含整个栈的JSON数组字符串
-    /// - None: 当键不存在时返回
+    /// 同 `set`
+    pub fn set_with_ttl<Q>(&self, key: &Q, value: V, ttl: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.push_entry(key, value, Some(ttl), BTreeMap::new())
+    }
+
+    /// 将带存活时间和用户元数据的值压入指定键对应的栈顶
     ///
-    /// # 注意
-    /// 在极少数情况下可能因互斥锁污染导致panic，但会尝试恢复数据
-    pub fn get(&self, key: &str) -> Option<String> {
-        self.inner.get(key).and_then(|stack| {
-            match stack.lock() {
-                Ok(guard) => serde_json::to_string(&*guard).ok(),
-                Err(poisoned) => {
-                    // 从被污染的锁中恢复数据
-                    let guard = poisoned.into_inner();
-                    serde_json::to_string(&*guard).ok()
-                }
-            }
-        })
+    /// # 参数
+    /// - key: 栈的键名
+    /// - value: 要压入的值
+    /// - options: 存活时间和用户元数据，未指定存活时间时使用默认存活时间
+    ///
+    /// # 返回值
+    /// 同 `set`
+    pub fn set_with<Q>(&self, key: &Q, value: V, options: EntryOptions) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let ttl = options.ttl.or_else(|| self.default_ttl());
+        self.push_entry(key, value, ttl, options.metadata)
     }
 
-    /// 获取过滤后的栈快照
+    /// 压入一个值，返回的守卫被丢弃时移除该值，可用作影子调用栈
     ///
     /// # 参数
-    /// - key_filter: 键名包含的过滤字符串
+    /// - key: 栈的键名
+    /// - value: 要压入的值
     ///
     /// # 返回值
-    /// - Some(String): 包含过滤结果的JSON数组字符串，每个元素是[键名, 栈内容数组]
-    /// - None: 当序列化失败时返回
+    /// 作用域守卫，通过压入序号识别自己的值，丢弃顺序与压入顺序不同时也只移除自己的值
     ///
     /// # 注意
-    /// - 获取时会克隆整个栈内容，可能影响性能
-    /// - 在极少数情况下可能因互斥锁污染导致panic，但会尝试恢复数据
-    pub fn iter(&self, key_filter: &str) -> Option<String> {
-        let r: Vec<(String, Vec<String>)> = self
-            .inner
-            .iter()
-            .filter(|entry| entry.key().contains(key_filter))
-            .map(|entry| {
-                match entry.value().lock() {
-                    Ok(stack) => (entry.key().clone(), stack.clone()),
-                    Err(poisoned) => {
-                        // 从被污染的锁中恢复数据
-                        let stack = poisoned.into_inner();
-                        (entry.key().clone(), stack.clone())
-                    }
-                }
-            })
-            .collect();
-        serde_json::to_string(&r).ok()
+    /// - 值的存活时间和容量限制与 `set` 相同，栈已满且新值被丢弃时守卫不做任何事
+    /// - 开启游程编码时作用域值不与相邻的相同值合并
+    /// - 值被 `move_top`/`swap_stacks` 移到其它键后，守卫不再移除它
+    pub fn push_scoped<Q>(&self, key: &Q, value: V) -> ScopeGuard<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let limit = self.capacity(key);
+        let seq = self.write_or_insert(key, |key, stack| {
+            let mut meta = EntryMeta::new(self.default_ttl(), BTreeMap::new());
+            meta.scoped = true;
+            let seq = meta.seq;
+            self.push_value(key, stack, value, meta, limit)
+                .then_some(seq)
+        });
+        self.after_write();
+        if seq.is_some() {
+            self.waiters.notify();
+        }
+        ScopeGuard::new(self, key.to_owned(), seq)
     }
 
     /// 删除指定键对应的整个栈
@@ -121,95 +297,3460 @@ impl StackStore {
     /// # 返回值
     /// - true: 成功删除存在的键
     /// - false: 键不存在
-    pub fn del_stack(&self, key: &str) -> bool {
-        self.inner.remove(key).is_some()
+    pub fn del_stack<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.inner.remove(key) {
+            Some((key, stack)) => {
+                self.log(|| Record::Delete { key: &key });
+                self.listeners.emit(|| Event::Deleted { key: &key });
+                let stack = into_stack(stack);
+                self.memory.fetch_sub(stack.bytes(), Ordering::Relaxed);
+                !stack.is_expired(Instant::now())
+            }
+            None => false,
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// 弹出指定键对应栈的栈顶元素
+    ///
+    /// # 返回值
+    /// - Some(V): 被弹出的栈顶值
+    /// - None: 键不存在或栈为空
+    ///
+    /// # 注意
+    /// 栈被弹空后键仍然保留，如需移除请调用 `del_stack`
+    pub fn pop<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let size = self.sizer.value;
+        self.access_key(key, Access::Write, |key, stack| {
+            let value = stack.pop(size)?;
+            self.log(|| Record::Pop { key, n: 1 });
+            self.listeners.emit(|| Event::Popped { key, count: 1 });
+            Some(value)
+        })
+        .flatten()
+    }
 
-    #[test]
-    fn test_set_and_get() {
-        let store = StackStore::new();
-        store.set("test", "value1".into());
-        store.set("test", "value2".into());
+    /// 弹出指定键对应栈的栈顶元素，栈为空时阻塞等待，直到有值压入或超时
+    ///
+    /// # 参数
+    /// - key: 栈的键名，键不存在时等待其被创建
+    /// - timeout: 最长等待时间
+    ///
+    /// # 返回值
+    /// - Some(V): 被弹出的栈顶值
+    /// - None: 超时
+    ///
+    /// # 注意
+    /// - 多个线程等待同一个键时，每个值只会被其中一个线程弹出
+    /// - 任何键压入新值都会唤醒所有等待者重新尝试，等待者很多时开销较大
+    pub fn pop_wait<Q>(&self, key: &Q, timeout: Duration) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.pop(key).or_else(|| {
+            self.waiters
+                .wait_until(Instant::now() + timeout, || self.pop(key))
+        })
+    }
 
-        let value = store.get("test").unwrap();
-        assert_eq!(value, r#"["value1","value2"]"#);
+    /// 异步弹出指定键对应栈的栈顶元素，栈为空时等待有值压入
+    ///
+    /// # 参数
+    /// - key: 栈的键名，键不存在时等待其被创建
+    ///
+    /// # 返回值
+    /// 弹出栈顶值的Future，基于Waker唤醒，不依赖特定的异步运行时
+    ///
+    /// # 注意
+    /// 需要超时时配合运行时的超时功能使用，Future被丢弃即取消等待
+    pub fn pop_async<'a, Q>(&'a self, key: &'a Q) -> PopFuture<'a, K, V, Q>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        PopFuture::new(self, key)
     }
 
-    #[test]
-    fn test_get_non_existent_key() {
-        let store = StackStore::new();
-        assert!(store.get("nonexistent").is_none());
+    /// 获取指定键对应栈的元素个数
+    ///
+    /// # 返回值
+    /// 栈中元素个数，键不存在时返回0
+    pub fn len<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.access(key, Access::Peek, |stack| stack.len())
+            .unwrap_or(0)
     }
 
-    #[test]
-    fn test_iter_filter() {
-        let store = StackStore::new();
-        store.set("apple1", "fruit".into());
-        store.set("banana2", "fruit".into());
-        store.set("carrot3", "vegetable".into());
+    /// 从栈顶开始连续弹出最多n个元素
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    /// - n: 最多弹出的元素个数
+    ///
+    /// # 返回值
+    /// 被弹出的值，按弹出顺序排列（第一个元素为原栈顶）；键不存在时返回空数组
+    pub fn pop_n<Q>(&self, key: &Q, n: usize) -> Vec<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let size = self.sizer.value;
+        self.access_key(key, Access::Write, |key, stack| {
+            let popped = stack.pop_n(n, size);
+            if !popped.is_empty() {
+                self.log(|| Record::Pop {
+                    key,
+                    n: popped.len(),
+                });
+                self.listeners.emit(|| Event::Popped {
+                    key,
+                    count: popped.len(),
+                });
+            }
+            popped
+        })
+        .unwrap_or_default()
+    }
 
-        let results = store.iter("na");
-        assert_eq!(results, Some(r#"[["banana2",["fruit"]]]"#.to_string()));
+    /// 将栈截断为只保留栈底的n个元素
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    /// - n: 保留的元素个数
+    ///
+    /// # 返回值
+    /// 被移除的元素个数；键不存在或栈长度不超过n时返回0
+    pub fn truncate<Q>(&self, key: &Q, n: usize) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let size = self.sizer.value;
+        self.access_key(key, Access::Write, |key, stack| {
+            let removed = stack.truncate(n, size);
+            if removed > 0 {
+                self.log(|| Record::Truncate { key, n });
+                self.listeners.emit(|| Event::Popped {
+                    key,
+                    count: removed,
+                });
+            }
+            removed
+        })
+        .unwrap_or(0)
     }
 
-    #[test]
-    fn test_del_stack() {
-        let store = StackStore::new();
-        store.set("temp", "data".into());
-        assert!(store.del_stack("temp"));
-        assert!(!store.del_stack("temp"));
+    /// 清空指定键对应的栈，但保留键本身
+    ///
+    /// # 返回值
+    /// - true: 键存在并已清空
+    /// - false: 键不存在
+    pub fn clear<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.access_key(key, Access::Write, |key, stack| {
+            let count = stack.len();
+            stack.clear();
+            self.log(|| Record::Clear { key });
+            if count > 0 {
+                self.listeners.emit(|| Event::Popped { key, count });
+            }
+        })
+        .is_some()
     }
 
-    #[test]
-    fn test_concurrent_access() {
-        let store = Arc::new(StackStore::new());
+    /// 栈为空时压入值
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    /// - value: 要压入的值
+    ///
+    /// # 返回值
+    /// - true: 值已保存
+    /// - false: 栈不为空，或栈已满且容量策略丢弃了新值
+    ///
+    /// # 注意
+    /// 键不存在或已过期时视为空栈，检查与压入是原子的
+    pub fn set_if_absent<Q>(&self, key: &Q, value: V) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.push_if(
+            key,
+            value,
+            self.default_ttl(),
+            BTreeMap::new(),
+            |stack, _| stack.is_empty(),
+        )
+    }
 
-        // 创建多个线程同时写入数据
-        let mut handles = vec![];
-        for i in 0..10 {
-            let store = store.clone();
-            let handle = std::thread::spawn(move || {
-                store.set("counter", i.to_string());
-            });
-            handles.push(handle);
+    /// 栈的长度等于预期时，用新值替换整个栈
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    /// - expected_len: 预期的栈长度，键不存在时长度为0
+    /// - values: 新的栈内容，栈底在前
+    ///
+    /// # 返回值
+    /// - true: 已替换
+    /// - false: 栈长度与预期不符，栈不变
+    ///
+    /// # 注意
+    /// - 新值使用默认存活时间，并按容量限制逐个压入
+    /// - 预期长度不为0时不会创建不存在的键
+    pub fn compare_and_swap_stack<Q>(&self, key: &Q, expected_len: usize, values: Vec<V>) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let ttl = self.default_ttl();
+        let limit = self.capacity(key);
+        let swap = |key: &K, stack: &mut Stack<V>| {
+            let len = stack.len();
+            if len != expected_len {
+                return None;
+            }
+            stack.clear();
+            self.log(|| Record::Clear { key });
+            if len > 0 {
+                self.listeners.emit(|| Event::Popped { key, count: len });
+            }
+            let mut pushed = false;
+            for value in values {
+                let meta = EntryMeta::new(ttl, BTreeMap::new());
+                pushed |= self.push_value(key, stack, value, meta, limit);
+            }
+            Some(pushed)
+        };
+        let swapped = if expected_len == 0 {
+            self.write_or_insert(key, swap)
+        } else {
+            self.access_key(key, Access::Write, swap).flatten()
+        };
+        let Some(pushed) = swapped else {
+            return false;
+        };
+        self.after_write();
+        if pushed {
+            self.waiters.notify();
         }
+        true
+    }
 
-        // 等待所有线程完成
-        for handle in handles {
-            handle.join().unwrap();
+    /// 设置所有键默认的容量限制
+    ///
+    /// # 参数
+    /// - limit: 容量限制，None表示不限制
+    ///
+    /// # 注意
+    /// 新的限制在下一次压入时生效，不会立即裁剪已有的栈
+    pub fn set_default_capacity(&self, limit: Option<CapacityLimit>) {
+        *self
+            .default_limit
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = limit;
+    }
+
+    /// 为指定键设置容量限制，覆盖默认限制
+    ///
+    /// # 参数
+    /// - key: 栈的键名，键不存在时也可以设置
+    /// - limit: 容量限制，None表示移除该键的单独设置、恢复使用默认限制
+    ///
+    /// # 注意
+    /// - 按键设置的限制在删除栈后仍然保留
+    /// - 新的限制在下一次压入时生效，不会立即裁剪已有的栈
+    pub fn set_capacity<Q>(&self, key: &Q, limit: Option<CapacityLimit>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        match limit {
+            Some(limit) => {
+                self.limits.insert(key.to_owned(), limit);
+            }
+            None => {
+                self.limits.remove(key);
+            }
         }
+    }
 
-        let value = store.get("counter").unwrap();
-        // 验证所有值都被正确添加
-        assert!(value.contains("\"0\"") && value.contains("\"9\""));
+    /// 获取指定键当前生效的容量限制
+    ///
+    /// # 返回值
+    /// - Some(CapacityLimit): 该键单独设置的限制，没有则为默认限制
+    /// - None: 不限制
+    pub fn capacity<Q>(&self, key: &Q) -> Option<CapacityLimit>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.limits.get(key) {
+            Some(limit) => Some(*limit),
+            None => *self
+                .default_limit
+                .read()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        }
     }
 
-    #[test]
-    fn test_empty_stack() {
-        let store = StackStore::new();
-        // 测试空栈的序列化
-        store.set("empty", "".into());
-        let result = store.get("empty");
-        assert_eq!(result, Some(r#"[""]"#.to_string()));
+    /// 获取各溢出策略丢弃的值的统计
+    pub fn overflow_stats(&self) -> OverflowStats {
+        self.overflow.snapshot()
     }
 
-    #[test]
-    fn test_multiple_stacks() {
-        let store = StackStore::new();
-        store.set("stack1", "a".into());
-        store.set("stack1", "b".into());
-        store.set("stack2", "x".into());
-        store.set("stack2", "y".into());
+    /// 以借用方式访问指定键对应的整个栈，不克隆栈内容
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    /// - f: 访问函数，参数为从栈底到栈顶排列的栈内容
+    ///
+    /// # 返回值
+    /// - Some(R): 访问函数的返回值
+    /// - None: 键不存在
+    ///
+    /// # 注意
+    /// 访问函数执行期间持有该栈的互斥锁和所在分片的读锁，不要在其中再访问同一个StackStore
+    pub fn with_stack<Q, R>(&self, key: &Q, f: impl FnOnce(&[V]) -> R) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.access(key, Access::Read, |stack| f(&stack.as_slice()))
+    }
 
-        let stack1 = store.get("stack1").unwrap();
-        let stack2 = store.get("stack2").unwrap();
+    /// 设置键和值的字节数估算函数，用于内存统计和内存预算
+    ///
+    /// # 注意
+    /// - 默认按类型本身的大小(size_of)估算，不包含堆上分配的内存
+    /// - `StackStore::new()` 创建的字符串存储按字符串容量估算
+    /// - 应在压入任何值之前设置
+    pub fn with_size_fn(mut self, key: fn(&K) -> usize, value: fn(&V) -> usize) -> Self {
+        self.sizer = Sizer { key, value };
+        self
+    }
 
-        assert_eq!(stack1, r#"["a","b"]"#);
-        assert_eq!(stack2, r#"["x","y"]"#);
+    /// 获取所有键和值的估算字节数
+    pub fn memory_usage(&self) -> usize {
+        self.memory.load(Ordering::Relaxed)
+    }
+
+    /// 设置整个存储的内存预算
+    ///
+    /// # 参数
+    /// - budget: 内存预算，None表示不限制
+    ///
+    /// # 注意
+    /// 超出预算时按淘汰顺序整栈删除，直到降到 `low_water_bytes` 之下；设置后立即检查一次
+    pub fn set_memory_budget(&self, budget: Option<MemoryBudget>) {
+        *self
+            .budget
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = budget;
+        self.after_write();
+    }
+
+    /// 获取当前的内存预算
+    pub fn memory_budget(&self) -> Option<MemoryBudget> {
+        *self
+            .budget
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// 获取内存预算淘汰的统计
+    pub fn eviction_stats(&self) -> EvictionStats {
+        self.eviction.snapshot()
+    }
+
+    /// 设置通过 `set` 压入的值默认的存活时间
+    ///
+    /// # 参数
+    /// - ttl: 存活时间，None表示不过期
+    ///
+    /// # 注意
+    /// 只影响之后压入的值
+    pub fn set_default_ttl(&self, ttl: Option<Duration>) {
+        *self
+            .default_ttl
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = ttl;
+    }
+
+    /// 获取通过 `set` 压入的值默认的存活时间
+    pub fn default_ttl(&self) -> Option<Duration> {
+        *self
+            .default_ttl
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// 设置指定键的整个栈的存活时间，到期后整个栈被移除
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    /// - ttl: 从现在开始计算的存活时间
+    ///
+    /// # 返回值
+    /// - true: 设置成功
+    /// - false: 键不存在
+    ///
+    /// # 注意
+    /// 之后的压入不会延长栈的存活时间
+    pub fn expire<Q>(&self, key: &Q, ttl: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let expires_at = Instant::now() + ttl;
+        self.access(key, Access::Peek, |stack| {
+            stack.expires_at = Some(expires_at)
+        })
+        .is_some()
+    }
+
+    /// 取消指定键的整个栈的存活时间
+    ///
+    /// # 返回值
+    /// - true: 键存在
+    /// - false: 键不存在
+    pub fn clear_expire<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.access(key, Access::Peek, |stack| stack.expires_at = None)
+            .is_some()
+    }
+
+    /// 获取指定键的整个栈的剩余存活时间
+    ///
+    /// # 返回值
+    /// - Some(Duration): 剩余存活时间
+    /// - None: 键不存在或没有设置存活时间
+    pub fn ttl<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        self.access(key, Access::Peek, |stack| {
+            stack.expires_at.map(|at| at.saturating_duration_since(now))
+        })
+        .flatten()
+    }
+
+    /// 立即移除所有已过期的栈和值
+    ///
+    /// # 返回值
+    /// 被移除的值的个数，包括已过期的栈中的值
+    ///
+    /// # 注意
+    /// 过期的数据在访问时也会被自动移除，此方法用于主动回收长期不访问的键
+    pub fn purge_expired(&self) -> usize {
+        let now = Instant::now();
+        let mut removed = 0;
+        self.inner.retain(|key, stack| {
+            let stack = stack
+                .get_mut()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            let before = stack.bytes();
+            if stack.is_expired(now) {
+                self.log(|| Record::Delete { key });
+                self.emit_expired(key, stack.len());
+                self.listeners.emit(|| Event::Deleted { key });
+                self.memory.fetch_sub(before, Ordering::Relaxed);
+                removed += stack.len();
+                return false;
+            }
+            removed += self.purge(key, stack, now);
+            true
+        });
+        removed
+    }
+
+    /// 关闭追加写日志
+    ///
+    /// # 返回值
+    /// 之前开启的日志文件路径，未开启时返回None
+    pub fn close_journal(&self) -> Option<std::path::PathBuf> {
+        self.journal
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take()
+            .map(|journal| journal.path().to_path_buf())
+    }
+
+    /// 将追加写日志刷入磁盘
+    ///
+    /// # 注意
+    /// 每条记录写入后即交给操作系统，进程崩溃不会丢失；此方法用于防止系统掉电丢失
+    pub fn sync_journal(&self) -> io::Result<()> {
+        match &*self
+            .journal
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+        {
+            Some(journal) => journal.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// 获取追加写日志中写入失败的记录条数
+    pub fn journal_errors(&self) -> u64 {
+        self.journal
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .as_ref()
+            .map_or(0, |journal| journal.errors())
+    }
+
+    /// 开启日志时追加一条记录
+    fn log<'a>(&self, record: impl FnOnce() -> Record<&'a K, &'a V>)
+    where
+        K: 'a,
+        V: 'a,
+    {
+        if let Some(journal) = &*self
+            .journal
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+        {
+            journal.append(&record());
+        }
+    }
+
+    /// 注册修改通知的回调监听器
+    ///
+    /// # 参数
+    /// - listener: 回调函数，收到借用键值的事件
+    ///
+    /// # 返回值
+    /// 监听器编号，用于 `remove_listener`
+    ///
+    /// # 注意
+    /// - 回调在修改所在的线程上、持有该栈的锁时同步执行，同一个键的事件按修改顺序到达
+    /// - 回调应尽快返回，不要在其中访问同一个StackStore
+    /// - 容量策略丢弃的值不产生事件，见 `overflow_stats`
+    pub fn add_listener(
+        &self,
+        listener: impl Fn(&Event<&K, &V>) + Send + Sync + 'static,
+    ) -> ListenerId {
+        self.listeners.add(move |event| {
+            listener(event);
+            true
+        })
+    }
+
+    /// 移除回调监听器
+    ///
+    /// # 返回值
+    /// - true: 已移除
+    /// - false: 监听器不存在
+    pub fn remove_listener(&self, id: ListenerId) -> bool {
+        self.listeners.remove(id)
+    }
+
+    /// 有值过期时发送通知
+    fn emit_expired(&self, key: &K, count: usize) {
+        if count > 0 {
+            self.listeners.emit(|| Event::Expired { key, count });
+        }
+    }
+
+    /// 推进逻辑时钟
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// 压入新值，键不存在或已过期时创建新栈
+    fn push_entry<Q>(
+        &self,
+        key: &Q,
+        value: V,
+        ttl: Option<Duration>,
+        metadata: BTreeMap<String, String>,
+    ) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.push_if(key, value, ttl, metadata, |_, _| true)
+    }
+
+    /// 在栈满足条件时压入新值，键不存在或已过期时按空栈判断
+    fn push_if<Q>(
+        &self,
+        key: &Q,
+        value: V,
+        ttl: Option<Duration>,
+        metadata: BTreeMap<String, String>,
+        cond: impl FnOnce(&Stack<V>, &V) -> bool,
+    ) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let limit = self.capacity(key);
+        let pushed = self.write_or_insert(key, |key, stack| {
+            cond(stack, &value) && {
+                let meta = EntryMeta::new(ttl, metadata);
+                self.push_value(key, stack, value, meta, limit)
+            }
+        });
+        self.after_write();
+        if pushed {
+            self.waiters.notify();
+        }
+        pushed
+    }
+
+    /// 按容量限制将值压入栈顶，保存成功时写日志并发送通知
+    fn push_value(
+        &self,
+        key: &K,
+        stack: &mut Stack<V>,
+        value: V,
+        meta: EntryMeta,
+        limit: Option<CapacityLimit>,
+    ) -> bool {
+        let value = match self.interning {
+            Some(interning) => (interning.value)(&self.interner, value),
+            None => value,
+        };
+        let pushed = stack.push(value, meta, limit, self.sizer.value, &self.overflow);
+        if pushed {
+            if let Some(value) = stack.values.back() {
+                self.log(|| Record::Push { key, value });
+                self.listeners.emit(|| Event::Pushed { key, value });
+            }
+        }
+        pushed
+    }
+
+    /// 整个栈已过期时换成同一个键的新空栈
+    fn reset_expired(&self, key: &K, stack: &mut Stack<V>) {
+        self.log(|| Record::Delete { key });
+        self.emit_expired(key, stack.len());
+        self.listeners.emit(|| Event::Deleted { key });
+        let old = stack.reset(self.tick());
+        self.memory
+            .fetch_sub(old.bytes() - stack.bytes(), Ordering::Relaxed);
+    }
+
+    /// 修改指定键的栈，键不存在或已过期时先创建新栈
+    fn write_or_insert<Q, R>(&self, key: &Q, f: impl FnOnce(&K, &mut Stack<V>) -> R) -> R
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        // 首先尝试获取现有条目
+        if let Some(stack) = self.inner.get(key) {
+            let mut guard = lock_stack(&stack);
+            if self.prepare(stack.key(), &mut guard) {
+                return self.update(&mut guard, Access::Write, |s| f(stack.key(), s));
+            }
+        }
+
+        // 如果键不存在或已过期，通过entry在分片写锁内创建条目并压入，
+        // 避免多个线程同时创建同一个键时互相覆盖
+        let (mut stack, _) = self.insert_stack(key);
+        let (key, stack) = stack.pair_mut();
+        let stack = stack
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if !self.prepare(key, stack) {
+            self.reset_expired(key, stack);
+        }
+        self.update(stack, Access::Write, |s| f(key, s))
+    }
+
+    /// 键不存在时在分片写锁内创建空栈
+    ///
+    /// # 返回值
+    /// 键对应的条目，及是否为新创建的
+    fn insert_stack<Q>(&self, key: &Q) -> (RefMut<'_, K, Mutex<Stack<V>>>, bool)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let key = match self.interning {
+            Some(interning) => (interning.key)(&self.interner, key.to_owned()),
+            None => key.to_owned(),
+        };
+        let key_bytes = (self.sizer.key)(&key);
+        match self.inner.entry(key) {
+            Entry::Occupied(entry) => (entry.into_ref(), false),
+            Entry::Vacant(entry) => {
+                self.memory.fetch_add(key_bytes, Ordering::Relaxed);
+                let stack = Stack::new(key_bytes, self.tick(), self.rle);
+                (entry.insert(Mutex::new(stack)), true)
+            }
+        }
+    }
+
+    /// 访问指定键的栈
+    ///
+    /// # 返回值
+    /// - Some(R): 访问函数的返回值
+    /// - None: 键不存在或整个栈已过期，已过期的栈会被移除
+    fn access<Q, R>(&self, key: &Q, access: Access, f: impl FnOnce(&mut Stack<V>) -> R) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.access_key(key, access, |_, stack| f(stack))
+    }
+
+    /// 按压入序号查找值在栈中的位置
+    pub(crate) fn position_of(&self, key: &K, seq: u64) -> Option<usize> {
+        self.access(key, Access::Peek, |stack| stack.position(seq))
+            .flatten()
+    }
+
+    /// 按压入序号移除一个值
+    pub(crate) fn remove_seq(&self, key: &K, seq: u64) -> bool {
+        self.remove_where(key, |stack| stack.position(seq))
+    }
+
+    /// 移除find找到的位置上的一个值
+    fn remove_where<Q>(&self, key: &Q, find: impl FnOnce(&Stack<V>) -> Option<usize>) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let size = self.sizer.value;
+        self.access_key(key, Access::Write, |key, stack| {
+            let Some(index) = find(stack).filter(|&index| stack.remove(index, size)) else {
+                return false;
+            };
+            self.log(|| Record::Remove { key, index });
+            self.listeners.emit(|| Event::Popped { key, count: 1 });
+            true
+        })
+        .unwrap_or(false)
+    }
+
+    /// 访问指定键的栈，访问函数同时得到存储中的键
+    fn access_key<Q, R>(
+        &self,
+        key: &Q,
+        access: Access,
+        f: impl FnOnce(&K, &mut Stack<V>) -> R,
+    ) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let r = {
+            let stack = self.inner.get(key)?;
+            let mut guard = lock_stack(&stack);
+            self.prepare(stack.key(), &mut guard)
+                .then(|| self.update(&mut guard, access, |s| f(stack.key(), s)))
+        };
+        if r.is_none() {
+            self.remove_expired(key);
+        }
+        r
+    }
+
+    /// 访问栈之前移除已过期的值
+    ///
+    /// # 返回值
+    /// - true: 栈可以继续访问
+    /// - false: 整个栈已过期
+    fn prepare(&self, key: &K, stack: &mut Stack<V>) -> bool {
+        if !stack.may_expire() {
+            return true;
+        }
+        let now = Instant::now();
+        if stack.is_expired(now) {
+            return false;
+        }
+        self.purge(key, stack, now);
+        true
+    }
+
+    /// 移除栈中已过期的值，记录日志并同步内存统计
+    ///
+    /// # 返回值
+    /// 被移除的值的个数
+    fn purge(&self, key: &K, stack: &mut Stack<V>, now: Instant) -> usize {
+        let before = stack.bytes();
+        let runs = stack.purge(now, self.sizer.value);
+        if runs.is_empty() {
+            return 0;
+        }
+        let purged = runs.iter().map(|&(_, count)| count).sum();
+        self.log(|| Record::Expire { key, runs });
+        self.emit_expired(key, purged);
+        self.memory
+            .fetch_sub(before - stack.bytes(), Ordering::Relaxed);
+        purged
+    }
+
+    /// 读写栈，更新对应的逻辑时钟并同步内存统计
+    fn update<R>(
+        &self,
+        stack: &mut Stack<V>,
+        access: Access,
+        f: impl FnOnce(&mut Stack<V>) -> R,
+    ) -> R {
+        let before = stack.bytes();
+        let r = f(stack);
+        let after = stack.bytes();
+        if after > before {
+            self.memory.fetch_add(after - before, Ordering::Relaxed);
+        } else {
+            self.memory.fetch_sub(before - after, Ordering::Relaxed);
+        }
+        match access {
+            Access::Peek => {}
+            Access::Read => stack.last_used = self.tick(),
+            Access::Write => {
+                let tick = self.tick();
+                stack.last_used = tick;
+                stack.last_write = tick;
+            }
+        }
+        r
+    }
+
+    /// 移除已过期的整个栈
+    fn remove_expired<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        if let Some((key, stack)) = self
+            .inner
+            .remove_if(key, |_, stack| lock_stack(stack).is_expired(now))
+        {
+            let stack = into_stack(stack);
+            self.log(|| Record::Delete { key: &key });
+            self.emit_expired(&key, stack.len());
+            self.listeners.emit(|| Event::Deleted { key: &key });
+            self.memory.fetch_sub(stack.bytes(), Ordering::Relaxed);
+        }
+    }
+
+    /// 写入并释放栈锁之后的维护：执行内存预算，按需清理驻留字符串
+    fn after_write(&self) {
+        self.enforce_budget();
+        self.interner.sweep_if_due();
+    }
+
+    /// 超出内存预算时按淘汰顺序整栈删除，直到降到淘汰目标之下
+    ///
+    /// 每轮淘汰需要遍历并排序所有栈，一次淘汰到低于上限的目标，把这一开销分摊到之后的多次压入
+    fn enforce_budget(&self) {
+        let Some(budget) = self.memory_budget() else {
+            return;
+        };
+        if self.memory_usage() <= budget.max_bytes {
+            return;
+        }
+        // 其它线程正在淘汰时直接返回，避免重复淘汰
+        let Ok(_evicting) = self.evicting.try_lock() else {
+            return;
+        };
+
+        let recency = |stack: &Stack<V>| match budget.order {
+            EvictionOrder::LeastRecentlyUsed => stack.last_used,
+            EvictionOrder::LeastRecentlyWritten => stack.last_write,
+        };
+        let target = budget.low_water_bytes.min(budget.max_bytes);
+        loop {
+            let usage = self.memory_usage();
+            if usage <= target {
+                return;
+            }
+            // 按时间从旧到新选出足以降到目标之下的栈，记录最新一个的时钟作为淘汰界限
+            let mut candidates: Vec<(u64, usize)> = self
+                .inner
+                .iter()
+                .map(|entry| {
+                    let stack = lock_stack(entry.value());
+                    (recency(&stack), stack.bytes())
+                })
+                .collect();
+            candidates.sort_unstable();
+            let excess = usage - target;
+            let mut freed = 0;
+            let mut cutoff = None;
+            for (tick, bytes) in candidates {
+                freed += bytes;
+                cutoff = Some(tick);
+                if freed >= excess {
+                    break;
+                }
+            }
+            let Some(cutoff) = cutoff else {
+                return;
+            };
+
+            // 删除界限之前的栈；期间被访问过的栈会越过界限而保留
+            let mut evicted = false;
+            self.inner.retain(|key, stack| {
+                let stack = stack
+                    .get_mut()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                if recency(stack) > cutoff {
+                    return true;
+                }
+                self.log(|| Record::Delete { key });
+                self.listeners.emit(|| Event::Deleted { key });
+                let bytes = stack.bytes();
+                self.memory.fetch_sub(bytes, Ordering::Relaxed);
+                self.eviction.evicted_keys.fetch_add(1, Ordering::Relaxed);
+                self.eviction
+                    .evicted_values
+                    .fetch_add(stack.len() as u64, Ordering::Relaxed);
+                self.eviction
+                    .evicted_bytes
+                    .fetch_add(bytes as u64, Ordering::Relaxed);
+                evicted = true;
+                false
+            });
+            if !evicted {
+                return;
+            }
+        }
+    }
+}
+
+impl<K, V> StackStore<K, V>
+where
+    K: Hash + Eq,
+    V: PartialEq,
+{
+    /// 开启游程编码：相邻的相同值只保存一份并记录重复次数
+    ///
+    /// # 注意
+    /// - 只合并存活时间相同且没有用户元数据的值，使用默认存活时间的值之间可以合并；
+    ///   合并的值共享第一个值的压入序号、时间和线程，过期时间按最后一个值的压入时间计算
+    /// - 对 `get`/`iter`/`get_vec` 等接口透明，按展开后的内容返回；`get_compact`/`iter_compact` 返回紧凑格式
+    /// - `with_stack` 在有合并的值时需要展开为临时数组
+    /// - 应在压入任何值之前设置
+    pub fn with_rle(mut self) -> Self
+    where
+        V: Clone,
+    {
+        self.rle = Some(Rle::new());
+        self
+    }
+
+    /// 栈顶与新值不同时压入，用于合并连续重复的值
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    /// - value: 要压入的值
+    ///
+    /// # 返回值
+    /// - true: 值已保存
+    /// - false: 栈顶等于新值，或栈已满且容量策略丢弃了新值
+    ///
+    /// # 注意
+    /// 检查与压入是原子的，多个线程同时压入相同的值时只有一个成功
+    pub fn push_if_top_ne<Q>(&self, key: &Q, value: V) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.push_if(
+            key,
+            value,
+            self.default_ttl(),
+            BTreeMap::new(),
+            |stack, value| stack.values.back() != Some(value),
+        )
+    }
+
+    /// 栈顶等于old时替换为new
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    /// - old: 预期的栈顶值
+    /// - new: 新的栈顶值
+    ///
+    /// # 返回值
+    /// - true: 已替换
+    /// - false: 键不存在、栈为空或栈顶不等于old
+    ///
+    /// # 注意
+    /// 替换后的值保留原栈顶的元数据和存活时间
+    pub fn replace_top<Q>(&self, key: &Q, old: &V, new: V) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let size = self.sizer.value;
+        let replaced = self
+            .access_key(key, Access::Write, |key, stack| {
+                if stack.values.back() != Some(old) {
+                    return false;
+                }
+                stack.replace_top(new, size);
+                self.log(|| Record::Pop { key, n: 1 });
+                self.listeners.emit(|| Event::Popped { key, count: 1 });
+                if let Some(value) = stack.values.back() {
+                    self.log(|| Record::Push { key, value });
+                    self.listeners.emit(|| Event::Pushed { key, value });
+                }
+                true
+            })
+            .unwrap_or(false);
+        if replaced {
+            self.after_write();
+        }
+        replaced
+    }
+}
+
+impl<K, V> StackStore<K, V>
+where
+    K: Hash + Eq,
+    V: From<String>,
+{
+    /// 捕获当前线程的Rust调用栈，每帧一个值压入指定键，跳过本库和运行时的帧
+    ///
+    /// # 返回值
+    /// 压入的帧数，平台不支持捕获调用栈时为0
+    ///
+    /// # 注意
+    /// 不受 `RUST_BACKTRACE` 环境变量影响，总是捕获；需要调试信息才能得到文件和行号
+    pub fn capture_backtrace<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.capture_backtrace_with(key, BacktraceOptions::default())
+    }
+
+    /// 捕获当前线程的Rust调用栈压入指定键，按选项跳过帧
+    ///
+    /// # 返回值
+    /// 同 `capture_backtrace`
+    pub fn capture_backtrace_with<Q>(&self, key: &Q, options: BacktraceOptions) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.push_backtrace(key, &Backtrace::force_capture(), options)
+    }
+
+    /// 将已捕获的Rust调用栈压入指定键
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    /// - backtrace: 已捕获的调用栈，如 `Backtrace::capture()` 的结果
+    /// - options: 需要跳过的帧
+    ///
+    /// # 返回值
+    /// 压入的帧数，调用栈未被捕获（如 `RUST_BACKTRACE` 未开启）时为0
+    ///
+    /// # 注意
+    /// - 每帧格式为 `函数名 (文件:行号)`，没有位置信息时只有函数名
+    /// - 最外层的帧先压入，栈顶是最内层的帧，与调用顺序一致
+    /// - 所有帧在同一把锁内压入，不会与其它线程压入的值交错；每帧都受容量限制约束
+    pub fn push_backtrace<Q>(
+        &self,
+        key: &Q,
+        backtrace: &Backtrace,
+        options: BacktraceOptions,
+    ) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let frames = backtrace::frames(backtrace, options);
+        if frames.is_empty() {
+            return 0;
+        }
+        let limit = self.capacity(key);
+        let ttl = self.default_ttl();
+        let pushed = self.write_or_insert(key, |key, stack| {
+            frames
+                .into_iter()
+                .rev()
+                .map(|frame| {
+                    let meta = EntryMeta::new(ttl, BTreeMap::new());
+                    self.push_value(key, stack, V::from(frame), meta, limit)
+                })
+                .filter(|&pushed| pushed)
+                .count()
+        });
+        self.after_write();
+        if pushed > 0 {
+            self.waiters.notify();
+        }
+        pushed
+    }
+}
+
+impl<K, V> StackStore<K, V>
+where
+    K: Hash + Eq,
+    V: AsRef<str>,
+{
+    /// 将指定键中保存的JS调用栈文本解析为帧
+    ///
+    /// # 返回值
+    /// - Some(Vec<Frame>): 按值从栈底到栈顶的顺序，每个值中的帧按其文本中的顺序排列
+    /// - None: 当键不存在时返回
+    ///
+    /// # 注意
+    /// 每个值可以是单独一帧，也可以是包含多行的整段调用栈；不是调用栈帧的行（如错误信息）被跳过，
+    /// 支持的格式见 `Frame::parse`
+    pub fn get_frames<Q>(&self, key: &Q) -> Option<Vec<Frame>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.access(key, Access::Read, |stack| {
+            stack
+                .iter()
+                .flat_map(|value| Frame::parse_stack(value.as_ref()))
+                .collect()
+        })
+    }
+
+    /// 将指定键中保存的JS调用栈文本解析为帧，并按source map还原到源代码位置
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    /// - symbolizer: 查找并缓存source map的还原器，可在多次调用间复用
+    ///
+    /// # 返回值
+    /// - Some(Vec<SymbolizedFrame>): 帧的顺序同 `get_frames`，无法还原的帧保留原样并标注原因
+    /// - None: 当键不存在时返回
+    ///
+    /// # 注意
+    /// 先解析出帧再释放锁，加载source map不会阻塞对该键的访问
+    pub fn get_symbolized<Q>(
+        &self,
+        key: &Q,
+        symbolizer: &Symbolizer,
+    ) -> Option<Vec<SymbolizedFrame>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let frames = self.get_frames(key)?;
+        Some(
+            frames
+                .iter()
+                .map(|frame| symbolizer.symbolize(frame))
+                .collect(),
+        )
+    }
+}
+
+impl<K, V> StackStore<K, V>
+where
+    K: Hash + Eq + AsRef<str>,
+    V: AsRef<str>,
+{
+    /// 将过滤后的栈聚合为折叠栈，用于生成火焰图
+    ///
+    /// # 参数
+    /// - key_filter: 键名过滤条件，同 `iter`
+    /// - mode: 以每个键的整个栈还是每个值的调用栈文本作为一个样本
+    ///
+    /// # 返回值
+    /// 相同调用栈合并计数的折叠栈，`to_string` 得到 `a;b;c 次数` 格式，`write_svg` 生成火焰图
+    ///
+    /// # 注意
+    /// 逐个锁定栈并复制帧名，空栈不计入样本
+    pub fn fold(&self, key_filter: impl KeyMatch, mode: SampleMode) -> FoldedStacks {
+        let mut folded = FoldedStacks::new();
+        for entry in self.inner.iter() {
+            if !key_filter.is_match(entry.key().as_ref()) {
+                continue;
+            }
+            let mut stack = lock_stack(entry.value());
+            if !self.prepare(entry.key(), &mut stack) {
+                continue;
+            }
+            match mode {
+                SampleMode::Key => folded.add(stack.iter(), 1),
+                SampleMode::Value => {
+                    for (value, meta) in stack.runs() {
+                        folded.add(flame::js_frames(value.as_ref()), meta.count as u64);
+                    }
+                }
+            }
+        }
+        folded
+    }
+}
+
+impl<K, V> StackStore<K, V>
+where
+    K: Hash + Eq + Ord,
+{
+    /// 锁定一组键，在事务中修改它们的栈
+    ///
+    /// # 参数
+    /// - keys: 参与事务的键，重复的键只锁定一次
+    /// - f: 事务函数，通过 `Transaction` 读写这些键的栈
+    ///
+    /// # 返回值
+    /// 事务函数的返回值
+    ///
+    /// # 注意
+    /// - 不存在的键会先创建空栈，事务结束时仍为空则移除；已有的键即使被弹空也会保留，与 `pop` 相同
+    /// - 只读取的键不更新写入时间，不影响内存预算的淘汰顺序
+    /// - 所有事务都按键的大小顺序加锁，多个事务同时执行不会死锁
+    /// - 事务函数执行期间持有这些栈的锁，不要在其中再通过StackStore访问这些键
+    /// - 日志按单键操作记录，崩溃时日志尾部可能只包含事务的一部分修改
+    pub fn transaction<Q, R>(
+        &self,
+        keys: &[&Q],
+        f: impl FnOnce(&mut Transaction<'_, K, V>) -> R,
+    ) -> R
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Ord + ToOwned<Owned = K> + ?Sized,
+    {
+        let mut keys = keys.to_vec();
+        keys.sort_unstable();
+        keys.dedup();
+        // 只为不存在的键创建空栈，已有的键不更新访问时间；加锁前键可能又被删除，此时重新创建
+        let mut created = Vec::new();
+        let refs = loop {
+            for key in &keys {
+                if self.inner.contains_key(*key) {
+                    continue;
+                }
+                let (stack, is_new) = self.insert_stack(*key);
+                if is_new {
+                    created.push((*key, lock_stack(&stack).last_write));
+                }
+            }
+            let refs: Option<Vec<_>> = keys.iter().map(|key| self.inner.get(*key)).collect();
+            if let Some(refs) = refs {
+                break refs;
+            }
+        };
+        let mut guards: Vec<_> = refs.iter().map(|stack| lock_stack(stack.value())).collect();
+        let stacks = refs
+            .iter()
+            .zip(guards.iter_mut())
+            .map(|(entry, guard)| {
+                let stack = &mut **guard;
+                if !self.prepare(entry.key(), stack) {
+                    self.reset_expired(entry.key(), stack);
+                }
+                (entry.key(), stack)
+            })
+            .collect();
+
+        let mut transaction = Transaction::new(self, stacks);
+        let r = f(&mut transaction);
+        let pushed = transaction.pushed;
+        drop(transaction);
+        drop(guards);
+        drop(refs);
+
+        // 事务创建的键最终为空时移除，只读取的键不会留下空栈
+        for (key, tick) in created {
+            if let Some((key, stack)) = self
+                .inner
+                .remove_if(key, |_, stack| lock_stack(stack).is_empty())
+            {
+                let stack = into_stack(stack);
+                if stack.last_write != tick {
+                    self.log(|| Record::Delete { key: &key });
+                    self.listeners.emit(|| Event::Deleted { key: &key });
+                }
+                self.memory.fetch_sub(stack.bytes(), Ordering::Relaxed);
+            }
+        }
+        self.after_write();
+        if pushed {
+            self.waiters.notify();
+        }
+        r
+    }
+
+    /// 将src的栈顶元素原子地移动到dst的栈顶
+    ///
+    /// # 返回值
+    /// - true: 已移动
+    /// - false: src为空，或dst已满且容量策略会丢弃新值，此时两个栈都不变
+    ///
+    /// # 注意
+    /// 移动的值保留原有的元数据和存活时间
+    pub fn move_top<Q>(&self, src: &Q, dst: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Ord + ToOwned<Owned = K> + ?Sized,
+    {
+        self.transaction(&[src, dst], |tx| {
+            if tx.len(src) == 0 || !tx.accepts(dst) {
+                return false;
+            }
+            match tx.pop_meta(src) {
+                Some((value, meta)) => tx.push_meta(dst, value, meta),
+                None => false,
+            }
+        })
+    }
+
+    /// 原子地交换两个键的栈内容
+    ///
+    /// # 注意
+    /// 值连同其元数据一起交换；各键的容量限制和整栈存活时间不随之交换
+    pub fn swap_stacks<Q>(&self, a: &Q, b: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Ord + ToOwned<Owned = K> + ?Sized,
+    {
+        self.transaction(&[a, b], |tx| tx.swap(a, b));
+    }
+}
+
+impl<K, V> StackStore<K, V>
+where
+    K: Hash + Eq + Serialize,
+    V: Serialize,
+{
+    /// 将所有未过期的栈保存到快照文件
+    ///
+    /// # 参数
+    /// - path: 快照文件路径，先写入同目录的 `.tmp` 临时文件再原子替换
+    ///
+    /// # 注意
+    /// - 快照为JSON数组，每个元素是[键名, 栈内容数组]
+    /// - 只保存键和值，不保存元数据、存活时间和容量设置
+    /// - 逐个栈加锁写出，不克隆栈内容；不同栈之间不保证是同一时刻的状态
+    pub fn save_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        persist::write_snapshot(path.as_ref(), |writer| {
+            self.write_stacks(writer, OutputFormat::Json, |_| true)
+                .map(|_| ())
+        })
+    }
+
+    /// 将指定键对应的整个栈以JSON数组写出，格式与 `get` 相同
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    /// - writer: 输出目标
+    ///
+    /// # 返回值
+    /// - Ok(true): 已写出
+    /// - Ok(false): 键不存在，没有写出任何内容
+    ///
+    /// # 注意
+    /// 写出期间持有该栈的锁，不克隆栈内容；输出到文件或网络时建议使用 `BufWriter`
+    pub fn write_json<Q>(&self, key: &Q, writer: impl Write) -> io::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.write_as(key, OutputFormat::Json, writer)
+    }
+
+    /// 按指定格式写出指定键对应的整个栈
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    /// - format: 输出格式，`Json`/`PrettyJson` 只输出栈内容，其它格式同时输出键名
+    /// - writer: 输出目标
+    ///
+    /// # 返回值
+    /// - Ok(true): 已写出
+    /// - Ok(false): 键不存在，没有写出任何内容
+    pub fn write_as<Q>(&self, key: &Q, format: OutputFormat, writer: impl Write) -> io::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.access_key(key, Access::Read, |key, stack| {
+            format::write_one(writer, format, key, stack)
+        })
+        .transpose()
+        .map(|written| written.is_some())
+    }
+
+    /// 按指定格式获取指定键对应的整个栈
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    /// - format: 输出格式，`OutputFormat::Json` 时与 `get` 相同
+    ///
+    /// # 返回值
+    /// - Some(String): 格式化后的栈内容
+    /// - None: 当键不存在或序列化失败时返回
+    pub fn get_as<Q>(&self, key: &Q, format: OutputFormat) -> Option<String>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut out = Vec::new();
+        self.write_as(key, format, &mut out)
+            .ok()?
+            .then(|| String::from_utf8(out).ok())
+            .flatten()
+    }
+
+    /// 逐个写出所有匹配的栈
+    ///
+    /// # 返回值
+    /// 写出的栈个数
+    fn write_stacks(
+        &self,
+        writer: impl Write,
+        format: OutputFormat,
+        filter: impl Fn(&K) -> bool,
+    ) -> io::Result<usize> {
+        let mut stacks = StackWriter::new(writer, format)?;
+        for entry in self.inner.iter() {
+            if !filter(entry.key()) {
+                continue;
+            }
+            let mut stack = lock_stack(entry.value());
+            if !self.prepare(entry.key(), &mut stack) {
+                continue;
+            }
+            stacks.write(entry.key(), &stack)?;
+        }
+        stacks.finish()
+    }
+}
+
+impl<K, V> StackStore<K, V>
+where
+    K: Hash + Eq + AsRef<str> + Serialize,
+    V: Serialize,
+{
+    /// 将过滤后的栈逐个以JSON写出，格式与 `iter` 相同
+    ///
+    /// # 参数
+    /// - key_filter: 键名过滤条件，同 `iter`
+    /// - writer: 输出目标
+    ///
+    /// # 返回值
+    /// 写出的栈个数
+    ///
+    /// # 注意
+    /// - 每次只锁定一个栈并直接写出，不克隆栈内容，也不在内存中生成整个JSON字符串
+    /// - 不同栈之间不保证是同一时刻的状态
+    /// - 输出到文件或网络时建议使用 `BufWriter`，写出较慢时会延长持有锁的时间
+    pub fn write_iter_json(
+        &self,
+        key_filter: impl KeyMatch,
+        writer: impl Write,
+    ) -> io::Result<usize> {
+        self.write_iter_as(key_filter, OutputFormat::Json, writer)
+    }
+
+    /// 将过滤后的栈逐个按指定格式写出
+    ///
+    /// # 参数
+    /// - key_filter: 键名过滤条件，同 `iter`
+    /// - format: 输出格式，`Ndjson`/`NdjsonEntries` 可直接追加到日志文件
+    /// - writer: 输出目标
+    ///
+    /// # 返回值
+    /// 写出的栈个数
+    ///
+    /// # 注意
+    /// 与 `write_iter_json` 相同，每次只锁定一个栈
+    pub fn write_iter_as(
+        &self,
+        key_filter: impl KeyMatch,
+        format: OutputFormat,
+        writer: impl Write,
+    ) -> io::Result<usize> {
+        self.write_stacks(writer, format, |key| key_filter.is_match(key.as_ref()))
+    }
+
+    /// 按指定格式获取过滤后的栈
+    ///
+    /// # 参数
+    /// - key_filter: 键名过滤条件，同 `iter`
+    /// - format: 输出格式，`OutputFormat::Json` 时与 `iter` 相同
+    ///
+    /// # 返回值
+    /// - Some(String): 格式化后的内容，没有匹配的栈时 `Ndjson`/`NdjsonEntries`/`Text` 为空字符串
+    /// - None: 当序列化失败时返回
+    ///
+    /// # 注意
+    /// 不克隆栈内容，逐个栈写入返回的字符串
+    pub fn iter_as(&self, key_filter: impl KeyMatch, format: OutputFormat) -> Option<String> {
+        let mut out = Vec::new();
+        self.write_iter_as(key_filter, format, &mut out).ok()?;
+        String::from_utf8(out).ok()
+    }
+
+    /// 导出匹配的栈，可在panic等异常状态下调用
+    ///
+    /// # 参数
+    /// - writer: 输出目标
+    /// - key_filter: 键名过滤条件
+    /// - lock_timeout: 等待锁的总时长上限
+    ///
+    /// # 返回值
+    /// 导出结果统计
+    ///
+    /// # 注意
+    /// - 输出格式与 `iter` 相同，已过期的栈和值不导出，也不会被移除
+    /// - 所有锁只尝试获取，不会阻塞等待；超过 `lock_timeout` 后被占用的栈输出为 `[键名, null]`，
+    ///   被占用的分片整体跳过，因此当前线程持有锁时也不会死锁
+    /// - 被污染的锁照常读取
+    pub fn dump(
+        &self,
+        mut writer: impl Write,
+        key_filter: impl KeyMatch,
+        lock_timeout: Duration,
+    ) -> io::Result<DumpStats> {
+        let now = Instant::now();
+        let deadline = now + lock_timeout;
+        let mut stats = DumpStats::default();
+        let mut first = true;
+        writer.write_all(b"[")?;
+        for shard in self.inner.shards() {
+            let Some(shard) = dump::try_until(deadline, || shard.try_read()) else {
+                stats.locked_shards += 1;
+                continue;
+            };
+            for (key, stack) in shard.iter() {
+                if !key_filter.is_match(key.as_ref()) {
+                    continue;
+                }
+                let stack = dump::try_until(deadline, || dump::try_lock(stack.get()));
+                if stack.as_ref().is_some_and(|stack| stack.is_expired(now)) {
+                    continue;
+                }
+                if !first {
+                    writer.write_all(b",")?;
+                }
+                first = false;
+                match stack {
+                    Some(stack) => {
+                        let values: Vec<&V> = stack
+                            .runs()
+                            .filter(|(_, meta)| meta.expires_at().is_none_or(|at| at > now))
+                            .flat_map(|(value, meta)| std::iter::repeat_n(value, meta.count))
+                            .collect();
+                        serde_json::to_writer(&mut writer, &(key, &values))?;
+                        stats.stacks += 1;
+                        stats.values += values.len();
+                    }
+                    None => {
+                        serde_json::to_writer(&mut writer, &(key, ()))?;
+                        stats.locked_stacks += 1;
+                    }
+                }
+            }
+        }
+        writer.write_all(b"]")?;
+        writer.flush()?;
+        Ok(stats)
+    }
+}
+
+impl<K, V> StackStore<K, V>
+where
+    K: Hash + Eq + Clone + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    /// 从快照文件恢复数据
+    ///
+    /// # 参数
+    /// - path: `save_to` 保存的快照文件路径
+    ///
+    /// # 返回值
+    /// 恢复的值的个数
+    ///
+    /// # 注意
+    /// 值按原顺序压入对应的栈，追加在已有的值之后，并受容量限制和内存预算约束
+    pub fn load_from(&self, path: impl AsRef<Path>) -> io::Result<usize> {
+        let stacks: Vec<(K, Vec<V>)> = persist::read_snapshot(path.as_ref())?;
+        let mut count = 0;
+        for (key, values) in stacks {
+            for value in values {
+                self.set(&key, value);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// 开启追加写日志：先重放日志文件中的已有记录，之后的修改都追加写入该文件
+    ///
+    /// # 参数
+    /// - path: 日志文件路径，不存在时创建
+    ///
+    /// # 返回值
+    /// 重放的记录条数
+    ///
+    /// # 注意
+    /// - 记录压入、弹出、截断、清空、删除栈和作用域值的移除，以及因过期和内存预算被移除的栈；
+    ///   值单独过期时记录被移除的位置，元数据和存活时间不记录
+    /// - 日志尾部不完整或校验失败的记录视为崩溃时未写完，会被截断
+    /// - 容量限制应在开启日志之前设置，以便重放的结果与原来一致
+    /// - 应在启动时调用，重放期间其它线程的修改不会写入日志
+    pub fn open_journal(&self, path: impl AsRef<Path>) -> io::Result<usize> {
+        self.close_journal();
+        let (journal, count) = Journal::open(path.as_ref(), |record| match record {
+            Record::Push { key, value } => {
+                self.set(&key, value);
+            }
+            Record::Pop { key, n } => {
+                self.pop_n(&key, n);
+            }
+            Record::Truncate { key, n } => {
+                self.truncate(&key, n);
+            }
+            Record::Remove { key, index } => {
+                self.remove_where(&key, |_| Some(index));
+            }
+            Record::Expire { key, runs } => {
+                let size = self.sizer.value;
+                self.access(&key, Access::Write, |stack| {
+                    for (index, count) in runs {
+                        for _ in 0..count {
+                            stack.remove(index, size);
+                        }
+                    }
+                });
+            }
+            Record::Clear { key } => {
+                self.clear(&key);
+            }
+            Record::Delete { key } => {
+                self.del_stack(&key);
+            }
+        })?;
+        *self
+            .journal
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(journal);
+        Ok(count)
+    }
+}
+
+impl<K, V> StackStore<K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    /// 查看指定键对应栈的栈顶元素（不弹出）
+    ///
+    /// # 返回值
+    /// - Some(V): 栈顶值的克隆
+    /// - None: 键不存在或栈为空
+    pub fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.access(key, Access::Read, |stack| stack.values.back().cloned())
+            .flatten()
+    }
+
+    /// 获取指定键对应的整个栈的克隆
+    ///
+    /// # 返回值
+    /// - Some(Vec<V>): 从栈底到栈顶排列的栈内容
+    /// - None: 键不存在
+    pub fn get_vec<Q>(&self, key: &Q) -> Option<Vec<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.access(key, Access::Read, |stack| stack.iter().cloned().collect())
+    }
+
+    /// 获取指定键对应的整个栈的值及其元数据
+    ///
+    /// # 返回值
+    /// - Some(Vec<StackEntry<V>>): 从栈底到栈顶排列的值及其压入序号、时间、线程和用户元数据
+    /// - None: 键不存在
+    pub fn get_entries<Q>(&self, key: &Q) -> Option<Vec<StackEntry<V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.access(key, Access::Read, |stack| stack.entries())
+    }
+}
+
+impl<K, V> StackStore<K, V>
+where
+    K: Hash + Eq + AsRef<str> + Clone,
+    V: Clone,
+{
+    /// 订阅匹配键的修改事件
+    ///
+    /// # 参数
+    /// - key_filter: 键名过滤条件
+    /// - capacity: 事件通道的容量，至少为1，传入0时按1处理
+    ///
+    /// # 返回值
+    /// 事件流，可当作 `Receiver<Event<K, V>>` 使用
+    ///
+    /// # 注意
+    /// - 通道已满时新事件被丢弃，不会阻塞修改操作，丢弃的个数见 `Subscription::dropped`
+    /// - 事件中的键和值是克隆的
+    /// - 事件流被丢弃后，订阅在下一个匹配的事件时自动取消
+    pub fn subscribe(
+        &self,
+        key_filter: impl KeyMatch + Send + Sync + 'static,
+        capacity: usize,
+    ) -> Subscription<K, V>
+    where
+        K: Send + 'static,
+        V: Send + 'static,
+    {
+        let (subscription, listener) = event::subscription(key_filter, capacity);
+        self.listeners.add(listener);
+        subscription
+    }
+
+    /// 获取过滤后的栈快照
+    ///
+    /// # 参数
+    /// - key_filter: 键名过滤条件，`&str` 按子字符串匹配，也可以是 `KeyFilter` 或 `Fn(&str) -> bool` 闭包
+    ///
+    /// # 返回值
+    /// 每个元素是(键名, 栈内容)，栈内容从栈底到栈顶排列
+    ///
+    /// # 注意
+    /// 获取时会克隆整个栈内容，可能影响性能
+    pub fn snapshot(&self, key_filter: impl KeyMatch) -> Vec<(K, Vec<V>)> {
+        self.collect(key_filter, |stack| stack.iter().cloned().collect())
+    }
+
+    /// 获取过滤后的栈快照，包含每个值的元数据
+    ///
+    /// # 参数
+    /// - key_filter: 键名过滤条件，同 `snapshot`
+    ///
+    /// # 返回值
+    /// 每个元素是(键名, 值及其元数据)，从栈底到栈顶排列
+    pub fn snapshot_entries(&self, key_filter: impl KeyMatch) -> Vec<(K, Vec<StackEntry<V>>)> {
+        self.collect(key_filter, |stack| stack.entries())
+    }
+
+    /// 分页遍历匹配过滤条件的栈
+    ///
+    /// # 参数
+    /// - key_filter: 键名过滤条件，同 `snapshot`
+    /// - cursor: 上一页返回的游标，None表示从头开始
+    /// - limit: 每页最多返回的键数，至少为1
+    ///
+    /// # 返回值
+    /// 本页的(键名, 栈内容)及下一页的游标
+    ///
+    /// # 注意
+    /// 从游标所在的分片继续，每页只读取返回的键所在的分片；选键时只持有分片读锁比较键名，
+    /// 不克隆也不锁定各个栈，只克隆本页的栈
+    pub fn scan(
+        &self,
+        key_filter: impl KeyMatch,
+        cursor: Option<&ScanCursor>,
+        limit: usize,
+    ) -> ScanPage<K, V> {
+        self.scan_top(key_filter, cursor, limit, usize::MAX)
+    }
+
+    /// 分页遍历匹配过滤条件的栈，每个栈只返回栈顶的若干个值
+    ///
+    /// # 参数
+    /// - key_filter: 键名过滤条件，同 `snapshot`
+    /// - cursor: 上一页返回的游标，None表示从头开始
+    /// - limit: 每页最多返回的键数，至少为1
+    /// - max_entries: 每个栈最多返回的值的个数，从栈顶开始计算，结果仍按栈底在前排列
+    ///
+    /// # 返回值
+    /// 本页的(键名, 栈内容)及下一页的游标
+    pub fn scan_top(
+        &self,
+        key_filter: impl KeyMatch,
+        cursor: Option<&ScanCursor>,
+        limit: usize,
+        max_entries: usize,
+    ) -> ScanPage<K, V> {
+        let limit = limit.max(1);
+        let start = cursor.map_or(0, ScanCursor::shard);
+        // (分片, 键名)，按遍历顺序排列
+        let mut candidates: Vec<(usize, K)> = Vec::with_capacity(limit);
+        for (index, shard) in self.inner.shards().iter().enumerate().skip(start) {
+            let need = limit - candidates.len();
+            // 大顶堆中保留本分片中游标之后最靠前的need个键
+            let mut heap: BinaryHeap<Candidate<K>> = BinaryHeap::with_capacity(need + 1);
+            for (key, _) in shard.read().iter() {
+                let name = key.as_ref();
+                if !key_filter.is_match(name)
+                    || cursor.is_some_and(|cursor| !cursor.is_before(index, name))
+                {
+                    continue;
+                }
+                if heap.len() == need && heap.peek().is_some_and(|max| max.0.as_ref() < name) {
+                    continue;
+                }
+                heap.push(Candidate(key.clone()));
+                if heap.len() > need {
+                    heap.pop();
+                }
+            }
+            candidates.extend(
+                heap.into_sorted_vec()
+                    .into_iter()
+                    .map(|candidate| (index, candidate.0)),
+            );
+            if candidates.len() == limit {
+                break;
+            }
+        }
+
+        let next = candidates
+            .last()
+            .filter(|_| candidates.len() == limit)
+            .map(|(shard, key)| ScanCursor::new(*shard, key.as_ref()));
+        let items = candidates
+            .into_iter()
+            .filter_map(|(_, key)| {
+                let values = self.access(&key, Access::Peek, |stack| stack.top(max_entries))?;
+                Some((key, values))
+            })
+            .collect();
+        ScanPage { items, next }
+    }
+
+    /// 对过滤后的每个未过期的栈执行f，收集(键名, 结果)
+    fn collect<T>(&self, key_filter: impl KeyMatch, f: impl Fn(&mut Stack<V>) -> T) -> Vec<(K, T)> {
+        self.inner
+            .iter()
+            .filter(|entry| key_filter.is_match(entry.key().as_ref()))
+            .filter_map(|entry| {
+                let mut stack = lock_stack(entry.value());
+                self.prepare(entry.key(), &mut stack)
+                    .then(|| (entry.key().clone(), f(&mut stack)))
+            })
+            .collect()
+    }
+}
+
+impl<K, V> StackStore<K, V>
+where
+    K: Hash + Eq,
+    V: Serialize,
+{
+    /// 获取指定键对应的整个栈的JSON序列化字符串
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    ///
+    /// # 返回值
+    /// - Some(String): 包含整个栈的JSON数组字符串
+    /// - None: 当键不存在时返回
+    ///
+    /// # 注意
+    /// 在极少数情况下可能因互斥锁污染导致panic，但会尝试恢复数据
+    pub fn get<Q>(&self, key: &Q) -> Option<String>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.access(key, Access::Read, |stack| {
+            serde_json::to_string(&ValuesJson(stack)).ok()
+        })
+        .flatten()
+    }
+
+    /// 获取指定键对应的整个栈的紧凑JSON序列化字符串
+    ///
+    /// # 返回值
+    /// - Some(String): 形如 `[["frame",500],["main",1]]` 的数组，每个元素是[值, 连续重复次数]
+    /// - None: 当键不存在时返回
+    ///
+    /// # 注意
+    /// 未开启游程编码（见 `with_rle`）时每个值的重复次数均为1
+    pub fn get_compact<Q>(&self, key: &Q) -> Option<String>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.access(key, Access::Read, |stack| {
+            serde_json::to_string(&CompactJson(stack)).ok()
+        })
+        .flatten()
+    }
+}
+
+impl<K, V> StackStore<K, V>
+where
+    K: Hash + Eq,
+    V: Clone + Serialize,
+{
+    /// 获取指定键对应的整个栈的JSON序列化字符串，按需包含元数据
+    ///
+    /// # 参数
+    /// - key: 栈的键名
+    /// - fields: 需要输出的元数据字段
+    ///
+    /// # 返回值
+    /// - Some(String): 不包含任何元数据时与 `get` 相同；
+    ///   否则每个值输出为 `{"value": 值, "seq": .., "timestamp": .., ...}` 对象
+    /// - None: 当键不存在时返回
+    pub fn get_with<Q>(&self, key: &Q, fields: EntryFields) -> Option<String>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entries = self.get_entries(key)?;
+        serde_json::to_string(&StackJson {
+            entries: &entries,
+            fields,
+        })
+        .ok()
+    }
+}
+
+impl<K, V> StackStore<K, V>
+where
+    K: Hash + Eq + AsRef<str> + Clone + Serialize,
+    V: Clone + Serialize,
+{
+    /// 获取过滤后的栈快照的JSON序列化字符串
+    ///
+    /// # 参数
+    /// - key_filter: 键名过滤条件，`&str` 按子字符串匹配，也可以是 `KeyFilter` 或 `Fn(&str) -> bool` 闭包
+    ///
+    /// # 返回值
+    /// - Some(String): 包含过滤结果的JSON数组字符串，每个元素是[键名, 栈内容数组]
+    /// - None: 当序列化失败时返回
+    ///
+    /// # 注意
+    /// - 获取时会克隆整个栈内容，可能影响性能
+    /// - 在极少数情况下可能因互斥锁污染导致panic，但会尝试恢复数据
+    pub fn iter(&self, key_filter: impl KeyMatch) -> Option<String> {
+        serde_json::to_string(&self.snapshot(key_filter)).ok()
+    }
+
+    /// 获取过滤后的栈快照的紧凑JSON序列化字符串
+    ///
+    /// # 返回值
+    /// - Some(String): 每个元素是[键名, 栈内容]，栈内容格式见 `get_compact`
+    /// - None: 当序列化失败时返回
+    pub fn iter_compact(&self, key_filter: impl KeyMatch) -> Option<String> {
+        let snapshot = self.collect(key_filter, |stack| {
+            stack
+                .runs()
+                .map(|(value, meta)| (value.clone(), meta.count))
+                .collect::<Vec<_>>()
+        });
+        serde_json::to_string(&snapshot).ok()
+    }
+
+    /// 获取过滤后的栈快照的JSON序列化字符串，按需包含元数据
+    ///
+    /// # 参数
+    /// - key_filter: 键名过滤条件，同 `iter`
+    /// - fields: 需要输出的元数据字段
+    ///
+    /// # 返回值
+    /// - Some(String): 每个元素是[键名, 栈内容数组]，栈内容格式见 `get_with`
+    /// - None: 当序列化失败时返回
+    pub fn iter_with(&self, key_filter: impl KeyMatch, fields: EntryFields) -> Option<String> {
+        let snapshot = self.snapshot_entries(key_filter);
+        let r: Vec<(&K, StackJson<V>)> = snapshot
+            .iter()
+            .map(|(key, entries)| (key, StackJson { entries, fields }))
+            .collect();
+        serde_json::to_string(&r).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_and_get() {
+        let store = StackStore::new();
+        store.set("test", "value1".into());
+        store.set("test", "value2".into());
+
+        let value = store.get("test").unwrap();
+        assert_eq!(value, r#"["value1","value2"]"#);
+    }
+
+    #[test]
+    fn test_get_non_existent_key() {
+        let store = StackStore::new();
+        assert!(store.get("nonexistent").is_none());
+    }
+
+    #[test]
+    fn test_iter_filter() {
+        let store = StackStore::new();
+        store.set("apple1", "fruit".into());
+        store.set("banana2", "fruit".into());
+        store.set("carrot3", "vegetable".into());
+
+        let results = store.iter("na");
+        assert_eq!(results, Some(r#"[["banana2",["fruit"]]]"#.to_string()));
+    }
+
+    #[test]
+    fn test_capacity_drop_oldest() {
+        let store = StackStore::new();
+        store.set_capacity(
+            "ring",
+            Some(CapacityLimit::new(3, OverflowPolicy::DropOldest)),
+        );
+        for i in 0..5 {
+            assert!(store.set("ring", i.to_string()));
+        }
+
+        assert_eq!(store.get("ring").unwrap(), r#"["2","3","4"]"#);
+        assert_eq!(store.overflow_stats().dropped_oldest, 2);
+        // 按键设置的限制在删除栈后仍然保留
+        assert!(store.del_stack("ring"));
+        for i in 0..4 {
+            store.set("ring", i.to_string());
+        }
+        assert_eq!(store.len("ring"), 3);
+    }
+
+    #[test]
+    fn test_capacity_reject_and_evict() {
+        let store = StackStore::new();
+        store.set_default_capacity(Some(CapacityLimit::new(2, OverflowPolicy::RejectNewest)));
+        store.set_capacity(
+            "evict",
+            Some(CapacityLimit::new(2, OverflowPolicy::EvictKey)),
+        );
+
+        assert!(store.set("reject", "a".into()));
+        assert!(store.set("reject", "b".into()));
+        assert!(!store.set("reject", "c".into()));
+        assert_eq!(store.get("reject").unwrap(), r#"["a","b"]"#);
+
+        for v in ["a", "b", "c"] {
+            assert!(store.set("evict", v.into()));
+        }
+        assert_eq!(store.get("evict").unwrap(), r#"["c"]"#);
+
+        let stats = store.overflow_stats();
+        assert_eq!(stats.rejected_newest, 1);
+        assert_eq!(stats.evicted_values, 2);
+        assert_eq!(stats.evicted_keys, 1);
+        assert_eq!(stats.dropped_oldest, 0);
+
+        store.set_capacity("evict", None);
+        assert_eq!(
+            store.capacity("evict"),
+            Some(CapacityLimit::new(2, OverflowPolicy::RejectNewest))
+        );
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let store = StackStore::<u32, u64>::default().with_size_fn(|_| 4, |_| 8);
+        store.set(&1, 10);
+        store.set(&1, 20);
+        store.set(&2, 30);
+        assert_eq!(store.memory_usage(), 4 + 8 * 2 + 4 + 8);
+
+        store.pop(&1);
+        assert_eq!(store.memory_usage(), 4 + 8 + 4 + 8);
+        store.clear(&2);
+        assert_eq!(store.memory_usage(), 4 + 8 + 4);
+        store.del_stack(&1);
+        store.del_stack(&2);
+        assert_eq!(store.memory_usage(), 0);
+    }
+
+    #[test]
+    fn test_memory_budget_lru() {
+        let store = StackStore::<u32, u64>::default().with_size_fn(|_| 0, |_| 10);
+        store.set_memory_budget(Some(MemoryBudget::new(
+            30,
+            EvictionOrder::LeastRecentlyUsed,
+        )));
+        store.set(&1, 1);
+        store.set(&2, 2);
+        store.set(&3, 3);
+        // 读取使键1成为最近使用
+        assert_eq!(store.peek(&1), Some(1));
+        store.set(&4, 4);
+
+        // 超出上限后淘汰到上限的90%以下：最久未使用的键2和键3
+        assert_eq!(store.len(&2), 0);
+        assert_eq!(store.len(&3), 0);
+        assert_eq!(store.len(&1), 1);
+        assert_eq!(store.memory_usage(), 20);
+        let stats = store.eviction_stats();
+        assert_eq!(stats.evicted_keys, 2);
+        assert_eq!(stats.evicted_values, 2);
+        assert_eq!(stats.evicted_bytes, 20);
+
+        // 回到上限之前不再淘汰
+        store.set(&5, 5);
+        assert_eq!(store.eviction_stats().evicted_keys, 2);
+        assert_eq!(store.memory_usage(), 30);
+    }
+
+    #[test]
+    fn test_memory_budget_lrw() {
+        let store = StackStore::<u32, u64>::default().with_size_fn(|_| 0, |_| 10);
+        store.set(&1, 1);
+        store.set(&2, 2);
+        store.set(&3, 3);
+        // 只读不改变写入顺序
+        assert_eq!(store.peek(&1), Some(1));
+        store.set_memory_budget(Some(
+            MemoryBudget::new(20, EvictionOrder::LeastRecentlyWritten).with_low_water(20),
+        ));
+
+        assert_eq!(store.len(&1), 0);
+        assert_eq!(store.get_vec(&2), Some(vec![2]));
+        assert_eq!(store.get_vec(&3), Some(vec![3]));
+        assert_eq!(store.memory_usage(), 20);
+    }
+
+    #[test]
+    fn test_entry_ttl() {
+        let store = StackStore::new();
+        store.set("frames", "keep".into());
+        store.set_with_ttl("frames", "gone".into(), Duration::ZERO);
+        store.set_with_ttl("frames", "later".into(), Duration::from_secs(60));
+
+        // 过期的值在访问时被移除，栈和键保留
+        assert_eq!(store.get("frames").unwrap(), r#"["keep","later"]"#);
+        assert_eq!(store.len("frames"), 2);
+
+        store.set_default_ttl(Some(Duration::ZERO));
+        store.set("frames", "default".into());
+        assert_eq!(store.peek("frames"), Some("later".to_string()));
+        store.set_default_ttl(None);
+        assert_eq!(store.default_ttl(), None);
+    }
+
+    #[test]
+    fn test_key_ttl() {
+        let store = StackStore::new();
+        store.set("req", "a".into());
+        assert!(store.expire("req", Duration::from_secs(60)));
+        assert!(store.ttl("req").unwrap() <= Duration::from_secs(60));
+        assert!(store.clear_expire("req"));
+        assert_eq!(store.ttl("req"), None);
+        assert!(!store.expire("missing", Duration::ZERO));
+
+        store.expire("req", Duration::from_millis(20));
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(store.get("req"), None);
+        assert_eq!(store.memory_usage(), 0);
+
+        // 过期后再次压入会创建新栈
+        store.set("req", "b".into());
+        assert_eq!(store.get("req").unwrap(), r#"["b"]"#);
+        assert_eq!(store.ttl("req"), None);
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let store = StackStore::new();
+        store.set("a", "1".into());
+        store.set("a", "2".into());
+        store.expire("a", Duration::ZERO);
+        store.set("b", "1".into());
+        store.set_with_ttl("b", "2".into(), Duration::ZERO);
+        store.set("c", "1".into());
+
+        assert_eq!(store.purge_expired(), 3);
+        assert_eq!(store.purge_expired(), 0);
+        assert_eq!(store.snapshot("").len(), 2);
+        assert!(!store.del_stack("a"));
+
+        store.del_stack("b");
+        store.del_stack("c");
+        assert_eq!(store.memory_usage(), 0);
+    }
+
+    #[test]
+    fn test_entry_metadata() {
+        let store = Arc::new(StackStore::new());
+        store.set("a", "1".into());
+        store.set_with("b", "2".into(), EntryOptions::default().meta("req", "42"));
+        let handle = {
+            let store = store.clone();
+            std::thread::Builder::new()
+                .name("worker".into())
+                .spawn(move || store.set("a", "3".into()))
+                .unwrap()
+        };
+        handle.join().unwrap();
+
+        let a = store.get_entries("a").unwrap();
+        let b = store.get_entries("b").unwrap();
+        assert_eq!(a[0].value, "1");
+        // 压入序号跨键单调递增
+        assert!(a[0].seq < b[0].seq && b[0].seq < a[1].seq);
+        assert_eq!(a[1].thread_name.as_deref(), Some("worker"));
+        assert_ne!(a[0].thread_id, a[1].thread_id);
+        assert_eq!(b[0].metadata.get("req").map(String::as_str), Some("42"));
+        assert!(a[0].metadata.is_empty());
+
+        let snapshot = store.snapshot_entries("b");
+        assert_eq!(snapshot, vec![("b".to_string(), b.clone())]);
+    }
+
+    #[test]
+    fn test_get_with_fields() {
+        let store = StackStore::new();
+        store.set_with("k", "v".into(), EntryOptions::default().meta("m", "1"));
+
+        assert_eq!(store.get_with("k", EntryFields::NONE), store.get("k"));
+        assert_eq!(store.iter_with("k", EntryFields::NONE), store.iter("k"));
+
+        let seq = store.get_entries("k").unwrap()[0].seq;
+        let fields = EntryFields {
+            seq: true,
+            metadata: true,
+            ..EntryFields::NONE
+        };
+        assert_eq!(
+            store.get_with("k", fields).unwrap(),
+            format!(r#"[{{"value":"v","seq":{},"metadata":{{"m":"1"}}}}]"#, seq)
+        );
+
+        let all: serde_json::Value =
+            serde_json::from_str(&store.iter_with("", EntryFields::ALL).unwrap()).unwrap();
+        let entry = &all[0][1][0];
+        assert_eq!(entry["value"], "v");
+        assert!(entry["timestamp"].as_u64().unwrap() > 0);
+        assert!(entry["thread_id"].is_u64());
+        assert!(entry.get("thread_name").is_some());
+    }
+
+    #[test]
+    fn test_structured_reads() {
+        let store = StackStore::new();
+        store.set("server:1", "a".into());
+        store.set("server:1", "b".into());
+        store.set("client:1", "c".into());
+
+        assert_eq!(
+            store.get_vec("server:1"),
+            Some(vec!["a".into(), "b".into()])
+        );
+        assert_eq!(store.get_vec("missing"), None);
+        assert_eq!(
+            store.with_stack("server:1", |s| s.join(">")),
+            Some("a>b".into())
+        );
+        assert_eq!(store.with_stack("missing", |s| s.len()), None);
+
+        let snapshot = store.snapshot("server");
+        assert_eq!(
+            snapshot,
+            vec![("server:1".into(), vec!["a".into(), "b".into()])]
+        );
+    }
+
+    #[test]
+    fn test_iter_key_filter() {
+        let store = StackStore::new();
+        store.set("server:1:err", "a".into());
+        store.set("server:2:ok", "b".into());
+        store.set("myserver:3:err", "c".into());
+
+        let keys = |filter| {
+            let mut keys: Vec<String> = store
+                .snapshot(&filter)
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect();
+            keys.sort();
+            keys
+        };
+        assert_eq!(
+            keys(KeyFilter::prefix("server:")),
+            vec!["server:1:err", "server:2:ok"]
+        );
+        assert_eq!(keys(KeyFilter::glob("server:*:err")), vec!["server:1:err"]);
+        assert_eq!(
+            keys(KeyFilter::suffix(":err")),
+            vec!["myserver:3:err", "server:1:err"]
+        );
+        assert_eq!(keys(KeyFilter::exact("server:2:ok")), vec!["server:2:ok"]);
+        assert_eq!(keys(KeyFilter::All).len(), 3);
+
+        let results = store.iter(|key: &str| key.starts_with("my"));
+        assert_eq!(results, Some(r#"[["myserver:3:err",["c"]]]"#.to_string()));
+    }
+
+    #[test]
+    fn test_scan_pages() {
+        let store = StackStore::new();
+        for i in 0..25 {
+            store.set(&format!("key{}", i), "a".into());
+            store.set(&format!("key{}", i), "b".into());
+        }
+        store.set("other", "x".into());
+
+        let mut seen = Vec::new();
+        let mut cursor: Option<ScanCursor> = None;
+        loop {
+            let page = store.scan_top(KeyFilter::prefix("key"), cursor.as_ref(), 10, 1);
+            assert!(page.items.len() <= 10);
+            for (key, values) in page.items {
+                assert_eq!(values, vec!["b".to_string()]);
+                seen.push(key);
+            }
+            // 游标可以转换为字符串传递
+            cursor = match page.next {
+                Some(next) => Some(next.to_string().parse().unwrap()),
+                None => break,
+            };
+        }
+        seen.sort();
+        let mut expected: Vec<String> = (0..25).map(|i| format!("key{}", i)).collect();
+        expected.sort();
+        assert_eq!(seen, expected);
+
+        let page = store.scan("other", None, 10);
+        assert_eq!(page.items, vec![("other".into(), vec!["x".into()])]);
+        assert_eq!(page.next, None);
+        assert!(serde_json::to_string(&page)
+            .unwrap()
+            .contains(r#""next":null"#));
+    }
+
+    #[test]
+    fn test_del_stack() {
+        let store = StackStore::new();
+        store.set("temp", "data".into());
+        assert!(store.del_stack("temp"));
+        assert!(!store.del_stack("temp"));
+    }
+
+    #[test]
+    fn test_concurrent_access() {
+        let store = Arc::new(StackStore::new());
+
+        // 创建多个线程同时写入数据
+        let mut handles = vec![];
+        for i in 0..10 {
+            let store = store.clone();
+            let handle = std::thread::spawn(move || {
+                store.set("counter", i.to_string());
+            });
+            handles.push(handle);
+        }
+
+        // 等待所有线程完成
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let value = store.get("counter").unwrap();
+        // 验证所有值都被正确添加
+        assert!(value.contains("\"0\"") && value.contains("\"9\""));
+    }
+
+    #[test]
+    fn test_concurrent_create_keys() {
+        let store = Arc::new(StackStore::new());
+        let threads = 8;
+        let keys = 200;
+
+        // 所有线程同时向同一批新键压入数据
+        let barrier = Arc::new(std::sync::Barrier::new(threads));
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                let store = store.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    for k in 0..keys {
+                        store.set(&format!("key{}", k), format!("{}-{}", t, k));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        for k in 0..keys {
+            let key = format!("key{}", k);
+            assert_eq!(store.len(&key), threads, "key {} lost values", key);
+            let mut values = store.pop_n(&key, threads);
+            values.sort();
+            let mut expected: Vec<_> = (0..threads).map(|t| format!("{}-{}", t, k)).collect();
+            expected.sort();
+            assert_eq!(values, expected);
+        }
+    }
+
+    #[test]
+    fn test_empty_stack() {
+        let store = StackStore::new();
+        // 测试空栈的序列化
+        store.set("empty", "".into());
+        let result = store.get("empty");
+        assert_eq!(result, Some(r#"[""]"#.to_string()));
+    }
+
+    #[test]
+    fn test_multiple_stacks() {
+        let store = StackStore::new();
+        store.set("stack1", "a".into());
+        store.set("stack1", "b".into());
+        store.set("stack2", "x".into());
+        store.set("stack2", "y".into());
+
+        let stack1 = store.get("stack1").unwrap();
+        let stack2 = store.get("stack2").unwrap();
+
+        assert_eq!(stack1, r#"["a","b"]"#);
+        assert_eq!(stack2, r#"["x","y"]"#);
+    }
+
+    #[test]
+    fn test_typed_store() {
+        let store = StackStore::<u64, (u32, Vec<u8>)>::default();
+        store.set(&1, (10, vec![1, 2]));
+        store.set(&1, (20, vec![3]));
+        store.set(&2, (30, vec![]));
+
+        assert_eq!(store.len(&1), 2);
+        assert_eq!(store.get(&1).unwrap(), "[[10,[1,2]],[20,[3]]]");
+        assert_eq!(store.pop(&1), Some((20, vec![3])));
+        assert_eq!(store.peek(&2), Some((30, vec![])));
+        assert!(store.del_stack(&2));
+    }
+
+    #[test]
+    fn test_pop_and_peek() {
+        let store = StackStore::new();
+        store.set("frames", "a".into());
+        store.set("frames", "b".into());
+
+        assert_eq!(store.peek("frames"), Some("b".to_string()));
+        assert_eq!(store.len("frames"), 2);
+        assert_eq!(store.pop("frames"), Some("b".to_string()));
+        assert_eq!(store.pop("frames"), Some("a".to_string()));
+        assert_eq!(store.pop("frames"), None);
+        assert_eq!(store.peek("frames"), None);
+        // 弹空后键仍然存在
+        assert_eq!(store.get("frames"), Some("[]".to_string()));
+        assert_eq!(store.len("missing"), 0);
+    }
+
+    #[test]
+    fn test_pop_n_truncate_clear() {
+        let store = StackStore::new();
+        for v in ["a", "b", "c", "d", "e"] {
+            store.set("frames", v.into());
+        }
+
+        assert_eq!(store.pop_n("frames", 2), vec!["e", "d"]);
+        assert_eq!(store.pop_n("frames", 10), vec!["c", "b", "a"]);
+        assert!(store.pop_n("missing", 1).is_empty());
+
+        for v in ["a", "b", "c"] {
+            store.set("frames", v.into());
+        }
+        assert_eq!(store.truncate("frames", 1), 2);
+        assert_eq!(store.truncate("frames", 5), 0);
+        assert_eq!(store.get("frames").unwrap(), r#"["a"]"#);
+
+        assert!(store.clear("frames"));
+        assert!(!store.clear("missing"));
+        assert_eq!(store.len("frames"), 0);
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("pi_stash_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = temp_path("snapshot.json");
+        let store = StackStore::new();
+        store.set("a", "1".into());
+        store.set("a", "2".into());
+        store.set("b", "3".into());
+        store.save_to(&path).unwrap();
+
+        let restored = StackStore::new();
+        assert_eq!(restored.load_from(&path).unwrap(), 3);
+        assert_eq!(restored.get("a").unwrap(), r#"["1","2"]"#);
+        assert_eq!(restored.get("b").unwrap(), r#"["3"]"#);
+        std::fs::remove_file(&path).unwrap();
+        assert!(restored.load_from(&path).is_err());
+    }
+
+    #[test]
+    fn test_journal_replay() {
+        let path = temp_path("replay.journal");
+        let _ = std::fs::remove_file(&path);
+        let store = StackStore::new();
+        assert_eq!(store.open_journal(&path).unwrap(), 0);
+        for v in ["a", "b", "c", "d"] {
+            store.set("frames", v.into());
+        }
+        store.pop("frames");
+        store.truncate("frames", 2);
+        store.set("gone", "x".into());
+        store.del_stack("gone");
+        store.set("empty", "x".into());
+        store.clear("empty");
+        store.sync_journal().unwrap();
+        assert_eq!(store.close_journal(), Some(path.clone()));
+        assert_eq!(store.journal_errors(), 0);
+
+        let restored = StackStore::new();
+        assert_eq!(restored.open_journal(&path).unwrap(), 10);
+        assert_eq!(restored.get("frames").unwrap(), r#"["a","b"]"#);
+        assert_eq!(restored.get("gone"), None);
+        assert_eq!(restored.get("empty").unwrap(), "[]");
+
+        // 重放后的修改继续追加到同一个日志
+        restored.set("frames", "e".into());
+        restored.close_journal();
+        let again = StackStore::new();
+        assert_eq!(again.open_journal(&path).unwrap(), 11);
+        assert_eq!(again.get("frames").unwrap(), r#"["a","b","e"]"#);
+        again.close_journal();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_journal_torn_tail() {
+        let path = temp_path("torn.journal");
+        let _ = std::fs::remove_file(&path);
+        let store = StackStore::new();
+        store.open_journal(&path).unwrap();
+        store.set("frames", "a".into());
+        store.set("frames", "b".into());
+        store.close_journal();
+
+        // 模拟崩溃时最后一条记录只写了一半
+        let len = std::fs::metadata(&path).unwrap().len();
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len - 3).unwrap();
+        drop(file);
+
+        let restored = StackStore::new();
+        assert_eq!(restored.open_journal(&path).unwrap(), 1);
+        assert_eq!(restored.get("frames").unwrap(), r#"["a"]"#);
+        restored.set("frames", "c".into());
+        restored.close_journal();
+
+        let again = StackStore::new();
+        assert_eq!(again.open_journal(&path).unwrap(), 2);
+        assert_eq!(again.get("frames").unwrap(), r#"["a","c"]"#);
+        again.close_journal();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_journal_value_expiry() {
+        let path = temp_path("expiry.journal");
+        let _ = std::fs::remove_file(&path);
+        let store = StackStore::new();
+        store.open_journal(&path).unwrap();
+        store.set("frames", "a".into());
+        store.set_with_ttl("frames", "b".into(), Duration::from_millis(10));
+        store.set_with_ttl("frames", "c".into(), Duration::from_millis(10));
+        store.set("frames", "d".into());
+        store.set("frames", "e".into());
+        std::thread::sleep(Duration::from_millis(20));
+        // 过期的b、c被移除后再弹出，日志中的位置必须与移除后的栈一致
+        assert_eq!(store.pop("frames"), Some("e".into()));
+        store.set_with_ttl("frames", "f".into(), Duration::from_millis(10));
+        store.set("frames", "g".into());
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(store.purge_expired(), 1);
+        let live = store.get("frames").unwrap();
+        assert_eq!(live, r#"["a","d","g"]"#);
+        store.close_journal();
+
+        let restored = StackStore::new();
+        restored.open_journal(&path).unwrap();
+        assert_eq!(restored.get("frames").unwrap(), live);
+        restored.close_journal();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_dump_with_held_and_poisoned_locks() {
+        let store = Arc::new(StackStore::new());
+        store.set("held", "a".into());
+        store.set("poisoned", "b".into());
+        store.set("other", "c".into());
+
+        // 在with_stack的闭包中panic，锁被污染
+        let poisoner = Arc::clone(&store);
+        let _ = std::thread::spawn(move || {
+            poisoner.with_stack("poisoned", |_| panic!("poison"));
+        })
+        .join();
+
+        // 另一个线程持有锁期间导出
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        let holder = Arc::clone(&store);
+        let handle = std::thread::spawn(move || {
+            holder.with_stack("held", |_| {
+                locked_tx.send(()).unwrap();
+                let _ = done_rx.recv();
+            });
+        });
+        locked_rx.recv().unwrap();
+
+        let mut out = Vec::new();
+        let stats = store
+            .dump(&mut out, KeyFilter::All, Duration::from_millis(20))
+            .unwrap();
+        done_tx.send(()).unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(stats.stacks, 2);
+        assert_eq!(stats.values, 2);
+        assert_eq!(stats.locked_stacks, 1);
+        let mut dumped: Vec<(String, Option<Vec<String>>)> = serde_json::from_slice(&out).unwrap();
+        dumped.sort();
+        assert_eq!(
+            dumped,
+            vec![
+                ("held".to_string(), None),
+                ("other".to_string(), Some(vec!["c".to_string()])),
+                ("poisoned".to_string(), Some(vec!["b".to_string()])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_panic_hook_dump() {
+        let path = temp_path("panic_dump.json");
+        GLOBAL_STACK_STORE.set_str("panic_hook:frames", "main");
+        // 钩子是进程全局的，测试结束前恢复原来的钩子，避免其它测试中的panic也触发导出
+        let previous = std::panic::take_hook();
+        install_panic_hook(
+            DumpTarget::File(path.clone()),
+            KeyFilter::prefix("panic_hook:"),
+            Duration::from_millis(20),
+        );
+        let _ = std::thread::spawn(|| panic!("crash")).join();
+        let _ = std::panic::take_hook();
+        std::panic::set_hook(previous);
+
+        let dumped = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(dumped, r#"[["panic_hook:frames",["main"]]]"#);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_subscribe_zero_capacity() {
+        let store = StackStore::new();
+        let events = store.subscribe(KeyFilter::All, 0);
+        store.set("k", "a".into());
+        store.set("k", "b".into());
+        assert_eq!(events.try_iter().count(), 1);
+        assert_eq!(events.dropped(), 1);
+    }
+
+    #[test]
+    fn test_subscribe_events() {
+        let store = StackStore::new();
+        let events = store.subscribe(KeyFilter::prefix("js:"), 16);
+        store.set("js:main", "a".into());
+        store.set("js:main", "b".into());
+        store.set("other", "x".into());
+        store.pop("js:main");
+        store.clear("js:main");
+        store.del_stack("js:main");
+
+        let key = "js:main".to_string();
+        let received: Vec<_> = events.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![
+                Event::Pushed {
+                    key: key.clone(),
+                    value: "a".to_string()
+                },
+                Event::Pushed {
+                    key: key.clone(),
+                    value: "b".to_string()
+                },
+                Event::Popped {
+                    key: key.clone(),
+                    count: 1
+                },
+                Event::Popped {
+                    key: key.clone(),
+                    count: 1
+                },
+                Event::Deleted { key },
+            ]
+        );
+        assert_eq!(events.dropped(), 0);
+    }
+
+    #[test]
+    fn test_subscribe_drops_when_full() {
+        let store = StackStore::new();
+        let events = store.subscribe(KeyFilter::All, 2);
+        for v in ["a", "b", "c", "d"] {
+            store.set("k", v.into());
+        }
+        assert_eq!(events.try_iter().count(), 2);
+        assert_eq!(events.dropped(), 2);
+
+        // 事件流被丢弃后订阅自动取消
+        drop(events);
+        store.set("k", "e".into());
+        assert_eq!(store.listeners.len(), 0);
+    }
+
+    #[test]
+    fn test_listener_expired_events() {
+        let store = StackStore::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let id = store.add_listener(move |event: &Event<&String, &String>| {
+            sink.lock().unwrap().push(event.cloned());
+        });
+
+        store.set_with_ttl("k", "old".into(), Duration::from_millis(10));
+        store.set("k", "new".into());
+        store.set("gone", "x".into());
+        store.expire("gone", Duration::from_millis(10));
+        std::thread::sleep(Duration::from_millis(20));
+        store.purge_expired();
+
+        assert!(store.remove_listener(id));
+        assert!(!store.remove_listener(id));
+        store.set("k", "ignored".into());
+
+        let seen = seen.lock().unwrap();
+        let tail: Vec<_> = seen.iter().skip(3).cloned().collect();
+        assert_eq!(seen.len(), 6);
+        assert!(tail.contains(&Event::Expired {
+            key: "k".to_string(),
+            count: 1
+        }));
+        assert!(tail.contains(&Event::Expired {
+            key: "gone".to_string(),
+            count: 1
+        }));
+        assert!(tail.contains(&Event::Deleted {
+            key: "gone".to_string()
+        }));
+    }
+
+    #[test]
+    fn test_pop_wait() {
+        let store = Arc::new(StackStore::new());
+        assert_eq!(store.pop_wait("jobs", Duration::from_millis(10)), None);
+
+        let producer = Arc::clone(&store);
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            producer.set("other", "x".into());
+            producer.set("jobs", "job1".into());
+        });
+        assert_eq!(
+            store.pop_wait("jobs", Duration::from_secs(5)),
+            Some("job1".to_string())
+        );
+        handle.join().unwrap();
+        assert_eq!(store.waiters.count.load(Ordering::SeqCst), 0);
+    }
+
+    /// 最小的执行器：在当前线程上轮询，被唤醒前挂起线程
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        struct ThreadWaker(std::thread::Thread);
+        impl std::task::Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            std::thread::park();
+        }
+    }
+
+    #[test]
+    fn test_pop_async() {
+        let store = Arc::new(StackStore::new());
+        store.set("jobs", "ready".into());
+        assert_eq!(block_on(store.pop_async("jobs")), "ready");
+
+        let producer = Arc::clone(&store);
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            producer.set("jobs", "later".into());
+        });
+        assert_eq!(block_on(store.pop_async("jobs")), "later");
+        handle.join().unwrap();
+        assert_eq!(store.waiters.count.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_transaction() {
+        let store = StackStore::new();
+        store.set("a", "1".into());
+        let len = store.transaction(&["b", "a", "c", "a"], |tx| {
+            assert_eq!(tx.peek("a"), Some(&"1".to_string()));
+            tx.push("b", "2".into());
+            tx.push("c", "3".into());
+            let top = tx.pop("a").unwrap();
+            tx.push("c", top);
+            assert_eq!(*tx.values("c"), ["3", "1"]);
+            tx.len("c")
+        });
+        assert_eq!(len, 2);
+        assert_eq!(store.get("a").unwrap(), "[]");
+        assert_eq!(store.get("b").unwrap(), r#"["2"]"#);
+        assert_eq!(store.get("c").unwrap(), r#"["3","1"]"#);
+    }
+
+    #[test]
+    fn test_move_top_and_swap() {
+        let store = StackStore::new();
+        store.set_with(
+            "src",
+            "frame".into(),
+            EntryOptions::default().meta("lang", "js"),
+        );
+        let seq = store.get_entries("src").unwrap()[0].seq;
+
+        assert!(store.move_top("src", "dst"));
+        assert!(!store.move_top("src", "dst"));
+        let moved = store.get_entries("dst").unwrap();
+        assert_eq!(moved[0].value, "frame");
+        assert_eq!(moved[0].seq, seq);
+        assert_eq!(moved[0].metadata["lang"], "js");
+
+        // 目标已满且拒绝新值时不移动
+        store.set("src", "x".into());
+        store.set_capacity(
+            "dst",
+            Some(CapacityLimit::new(1, OverflowPolicy::RejectNewest)),
+        );
+        assert!(!store.move_top("src", "dst"));
+        assert_eq!(store.len("src"), 1);
+
+        let before = store.memory_usage();
+        store.swap_stacks("src", "dst");
+        assert_eq!(store.get("src").unwrap(), r#"["frame"]"#);
+        assert_eq!(store.get("dst").unwrap(), r#"["x"]"#);
+        assert_eq!(store.memory_usage(), before);
+    }
+
+    #[test]
+    fn test_transaction_missing_keys() {
+        let store = StackStore::new();
+        store.set("old", "1".into());
+        store.set("new", "2".into());
+        let before = store.memory_usage();
+        let last_write = |key: &str| lock_stack(&store.inner.get(key).unwrap()).last_write;
+        let written = last_write("old");
+
+        // 只读取的键不留下空栈，已有的键也不更新写入时间
+        assert!(!store.move_top("missing", "x"));
+        store.transaction(&["old", "ghost"], |tx| tx.len("old") + tx.len("ghost"));
+        assert_eq!(store.inner.len(), 2);
+        assert_eq!(store.get("x"), None);
+        assert_eq!(store.get("ghost"), None);
+        assert_eq!(store.memory_usage(), before);
+        assert_eq!(last_write("old"), written);
+
+        // 压入后又弹空的新键同样被移除
+        store.transaction(&["tmp"], |tx| {
+            tx.push("tmp", "x".into());
+            tx.pop("tmp")
+        });
+        assert_eq!(store.get("tmp"), None);
+        assert_eq!(store.memory_usage(), before);
+    }
+
+    #[test]
+    fn test_swap_wakes_waiters() {
+        let store = Arc::new(StackStore::new());
+        store.set("ready", "job".into());
+        let waiter = Arc::clone(&store);
+        let handle = std::thread::spawn(move || {
+            let start = Instant::now();
+            let value = waiter.pop_wait("jobs", Duration::from_secs(5));
+            (value, start.elapsed())
+        });
+        while store.waiters.count.load(Ordering::SeqCst) == 0 {
+            std::thread::yield_now();
+        }
+        store.swap_stacks("ready", "jobs");
+        let (value, elapsed) = handle.join().unwrap();
+        assert_eq!(value, Some("job".to_string()));
+        // 由交换唤醒，而不是等到超时
+        assert!(elapsed < Duration::from_secs(4));
+        assert_eq!(store.get("ready").unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_concurrent_move_top() {
+        let store = Arc::new(StackStore::new());
+        for i in 0..100 {
+            store.set("left", i.to_string());
+            store.set("right", i.to_string());
+        }
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let store = Arc::clone(&store);
+                std::thread::spawn(move || {
+                    let (src, dst) = if t % 2 == 0 {
+                        ("left", "right")
+                    } else {
+                        ("right", "left")
+                    };
+                    for _ in 0..500 {
+                        store.move_top(src, dst);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(store.len("left") + store.len("right"), 200);
+    }
+
+    #[test]
+    fn test_conditional_push() {
+        let store = StackStore::new();
+        assert!(store.set_if_absent("k", "a".into()));
+        assert!(!store.set_if_absent("k", "b".into()));
+        store.pop("k");
+        assert!(store.set_if_absent("k", "b".into()));
+
+        assert!(!store.push_if_top_ne("k", "b".into()));
+        assert!(store.push_if_top_ne("k", "c".into()));
+        assert!(store.push_if_top_ne("k", "b".into()));
+        assert!(store.push_if_top_ne("new", "x".into()));
+        assert_eq!(store.get("k").unwrap(), r#"["b","c","b"]"#);
+
+        assert!(!store.replace_top("k", &"c".to_string(), "d".into()));
+        assert!(store.replace_top("k", &"b".to_string(), "dd".into()));
+        assert!(!store.replace_top("missing", &"b".to_string(), "d".into()));
+        assert_eq!(store.get("k").unwrap(), r#"["b","c","dd"]"#);
+        let entries = store.get_entries("k").unwrap();
+        assert!(entries[1].seq < entries[2].seq);
+    }
+
+    #[test]
+    fn test_compare_and_swap_stack() {
+        let store = StackStore::new();
+        assert!(!store.compare_and_swap_stack("k", 1, vec!["a".into()]));
+        assert_eq!(store.get("k"), None);
+        assert!(store.compare_and_swap_stack("k", 0, vec!["a".into(), "b".into()]));
+        assert!(!store.compare_and_swap_stack("k", 0, vec!["x".into()]));
+        assert!(store.compare_and_swap_stack("k", 2, vec!["c".into()]));
+        assert_eq!(store.get("k").unwrap(), r#"["c"]"#);
+        assert_eq!(
+            store.memory_usage(),
+            string_size(&"k".into()) + string_size(&"c".into())
+        );
+    }
+
+    #[test]
+    fn test_concurrent_push_if_top_ne() {
+        let store = Arc::new(StackStore::new());
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let store = Arc::clone(&store);
+                std::thread::spawn(move || {
+                    for i in 0..100 {
+                        store.push_if_top_ne("frames", (i / 10).to_string());
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let frames = store.get_vec("frames").unwrap();
+        assert!(frames.windows(2).all(|w| w[0] != w[1]));
+    }
+
+    #[test]
+    fn test_rle() {
+        let store = StackStore::new().with_rle();
+        for _ in 0..500 {
+            store.set("js", "frame".into());
+        }
+        store.set("js", "main".into());
+
+        assert_eq!(store.len("js"), 501);
+        assert_eq!(
+            store.get_compact("js").unwrap(),
+            r#"[["frame",500],["main",1]]"#
+        );
+        assert_eq!(
+            store.iter_compact("js").unwrap(),
+            r#"[["js",[["frame",500],["main",1]]]]"#
+        );
+        assert_eq!(store.get_vec("js").unwrap().len(), 501);
+        assert_eq!(store.with_stack("js", |s| s.len()), Some(501));
+        assert_eq!(
+            store.memory_usage(),
+            string_size(&"js".into()) + string_size(&"frame".into()) + string_size(&"main".into())
+        );
+        let entries = store.get_entries("js").unwrap();
+        assert_eq!(entries[0].seq, entries[499].seq);
+        assert!(entries[499].seq < entries[500].seq);
+
+        assert_eq!(store.pop("js"), Some("main".to_string()));
+        assert_eq!(store.pop_n("js", 2), vec!["frame", "frame"]);
+        assert_eq!(store.truncate("js", 3), 495);
+        assert_eq!(store.get("js").unwrap(), r#"["frame","frame","frame"]"#);
+        assert!(store.replace_top("js", &"frame".to_string(), "top".into()));
+        assert_eq!(
+            store.get_compact("js").unwrap(),
+            r#"[["frame",2],["top",1]]"#
+        );
+    }
+
+    #[test]
+    fn test_rle_limits_and_ttl() {
+        let store = StackStore::new().with_rle();
+        store.set_capacity(
+            "js",
+            Some(CapacityLimit::new(3, OverflowPolicy::DropOldest)),
+        );
+        store.set("js", "a".into());
+        store.set("js", "a".into());
+        store.set("js", "b".into());
+        store.set("js", "b".into());
+        assert_eq!(store.get_compact("js").unwrap(), r#"[["a",1],["b",2]]"#);
+        assert_eq!(store.overflow_stats().dropped_oldest, 1);
+
+        // 存活时间不同或带用户元数据的值不合并
+        store.set_with_ttl("ttl", "x".into(), Duration::from_secs(60));
+        store.set_with_ttl("ttl", "x".into(), Duration::from_secs(60));
+        store.set_with_ttl("ttl", "x".into(), Duration::from_secs(30));
+        store.set_with("ttl", "x".into(), EntryOptions::default().meta("k", "v"));
+        assert_eq!(
+            store.get_compact("ttl").unwrap(),
+            r#"[["x",2],["x",1],["x",1]]"#
+        );
+
+        // 未开启时每个值单独保存
+        let plain = StackStore::new();
+        plain.set("js", "a".into());
+        plain.set("js", "a".into());
+        assert_eq!(plain.get_compact("js").unwrap(), r#"[["a",1],["a",1]]"#);
+    }
+
+    #[test]
+    fn test_rle_default_ttl() {
+        let store = StackStore::new().with_rle();
+        store.set_default_ttl(Some(Duration::from_millis(100)));
+        store.set("js", "a".into());
+        std::thread::sleep(Duration::from_millis(60));
+        store.set("js", "a".into());
+        assert_eq!(store.get_compact("js").unwrap(), r#"[["a",2]]"#);
+
+        // 合并后按最后一个值的压入时间过期
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(store.len("js"), 2);
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(store.len("js"), 0);
+    }
+
+    #[test]
+    fn test_interned_store() {
+        let store = StackStore::interned();
+        for i in 0..10 {
+            store.set_str(&format!("js:{i}"), "at foo (a.js:1:2)");
+        }
+        store.set(&Arc::from("js:0"), Arc::from("at foo (a.js:1:2)"));
+
+        let a = store.peek("js:0").unwrap();
+        let b = store.peek("js:9").unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        drop((a, b));
+        assert_eq!(
+            store.get("js:0").unwrap(),
+            r#"["at foo (a.js:1:2)","at foo (a.js:1:2)"]"#
+        );
+
+        let stats = store.intern_stats();
+        assert_eq!(stats.unique, 11);
+        assert_eq!(stats.references, 21);
+        assert_eq!(stats.bytes_saved, 10 * "at foo (a.js:1:2)".len());
+
+        for i in 0..10 {
+            store.del_stack(format!("js:{i}").as_str());
+        }
+        assert_eq!(store.sweep_interned(), 11);
+        assert_eq!(store.intern_stats(), InternStats::default());
+
+        // 新增足够多的字符串后，压入完成时自动清理不再使用的字符串
+        for i in 0..1024 {
+            store.set_str("js", &i.to_string());
+            store.pop("js");
+        }
+        // 清理时栈中仍有值"1022"，之后又新增了"1023"，连同键名共3个
+        assert_eq!(store.intern_stats().unique, 3);
+    }
+
+    #[test]
+    fn test_write_json() {
+        let store = StackStore::new().with_rle();
+        store.set("server:1", "a".into());
+        store.set("server:1", "a".into());
+        store.set("server:2", "b\"c".into());
+        store.set("client:1", "x".into());
+
+        let mut out = Vec::new();
+        assert!(store.write_json("server:1", &mut out).unwrap());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            store.get("server:1").unwrap()
+        );
+        assert!(!store.write_json("missing", &mut Vec::new()).unwrap());
+
+        let mut out = Vec::new();
+        let written = store
+            .write_iter_json(KeyFilter::prefix("server:"), &mut out)
+            .unwrap();
+        assert_eq!(written, 2);
+        let mut streamed: Vec<(String, Vec<String>)> = serde_json::from_slice(&out).unwrap();
+        let mut built: Vec<(String, Vec<String>)> =
+            serde_json::from_str(&store.iter(KeyFilter::prefix("server:")).unwrap()).unwrap();
+        streamed.sort();
+        built.sort();
+        assert_eq!(streamed, built);
+
+        let mut out = Vec::new();
+        assert_eq!(store.write_iter_json("none", &mut out).unwrap(), 0);
+        assert_eq!(out, b"[]");
+    }
+
+    #[test]
+    fn test_output_formats() {
+        let store = StackStore::new();
+        store.set("k", "main".into());
+        store.set("k", "foo\nbar".into());
+
+        assert_eq!(store.get_as("k", OutputFormat::Json), store.get("k"));
+        assert_eq!(
+            store.get_as("k", OutputFormat::PrettyJson).unwrap(),
+            "[\n  \"main\",\n  \"foo\\nbar\"\n]"
+        );
+        assert_eq!(
+            store.get_as("k", OutputFormat::Object).unwrap(),
+            r#"{"k":["main","foo\nbar"]}"#
+        );
+        assert_eq!(
+            store.get_as("k", OutputFormat::Ndjson).unwrap(),
+            "{\"key\":\"k\",\"values\":[\"main\",\"foo\\nbar\"]}\n"
+        );
+        assert_eq!(
+            store.get_as("k", OutputFormat::NdjsonEntries).unwrap(),
+            "{\"key\":\"k\",\"index\":0,\"value\":\"main\"}\n\
+             {\"key\":\"k\",\"index\":1,\"value\":\"foo\\nbar\"}\n"
+        );
+        assert_eq!(
+            store.get_as("k", OutputFormat::Text).unwrap(),
+            "k\n    foo\n    bar\n    main\n"
+        );
+        assert_eq!(store.get_as("missing", OutputFormat::Text), None);
+
+        store.set("j", "x".into());
+        let value: serde_json::Value =
+            serde_json::from_str(&store.iter_as("", OutputFormat::Object).unwrap()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({"k": ["main", "foo\nbar"], "j": ["x"]})
+        );
+        let pretty: serde_json::Value =
+            serde_json::from_str(&store.iter_as("", OutputFormat::PrettyJson).unwrap()).unwrap();
+        let compact: serde_json::Value =
+            serde_json::from_str(&store.iter_as("", OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(pretty, compact);
+        let lines = store.iter_as("", OutputFormat::NdjsonEntries).unwrap();
+        assert_eq!(lines.lines().count(), 3);
+        assert_eq!(store.iter_as("none", OutputFormat::Ndjson).unwrap(), "");
+        assert_eq!(
+            store.iter_as("none", OutputFormat::PrettyJson).unwrap(),
+            "[]"
+        );
+        assert_eq!(store.iter_as("none", OutputFormat::Object).unwrap(), "{}");
+        assert_eq!(
+            store.iter_as("j", OutputFormat::Text).unwrap(),
+            "j\n    x\n"
+        );
+    }
+
+    #[test]
+    fn test_output_format_numeric_keys() {
+        let store: StackStore<u32, u32> = StackStore::default();
+        store.set(&7, 1);
+        store.set(&7, 2);
+        assert_eq!(
+            store.get_as(&7, OutputFormat::Object).unwrap(),
+            r#"{"7":[1,2]}"#
+        );
+        assert_eq!(
+            store.get_as(&7, OutputFormat::Text).unwrap(),
+            "7\n    2\n    1\n"
+        );
+    }
+
+    #[test]
+    fn test_push_scoped() {
+        let store = StackStore::new();
+        {
+            let _main = store.push_scoped("calls", "main".into());
+            {
+                let _render = store.push_scoped("calls", "render".into());
+                assert_eq!(store.get_vec("calls").unwrap(), vec!["main", "render"]);
+            }
+            assert_eq!(store.get_vec("calls").unwrap(), vec!["main"]);
+        }
+        assert_eq!(store.len("calls"), 0);
+
+        // 乱序丢弃只移除各自的值
+        let a = store.push_scoped("calls", "a".into());
+        let b = store.push_scoped("calls", "b".into());
+        store.set("calls", "c".into());
+        assert!(a.is_active());
+        drop(a);
+        assert_eq!(store.get_vec("calls").unwrap(), vec!["b", "c"]);
+        // 值已被弹出时守卫什么也不做
+        store.pop("calls");
+        store.pop("calls");
+        assert!(!b.is_active());
+        store.set("calls", "d".into());
+        drop(b);
+        assert_eq!(store.get_vec("calls").unwrap(), vec!["d"]);
+    }
+
+    #[test]
+    fn test_push_scoped_unwind() {
+        let store = StackStore::new().with_rle();
+        let _outer = store.push_scoped("calls", "f".into());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _inner = store.push_scoped("calls", "f".into());
+            store.set("calls", "f".into());
+            assert_eq!(store.len("calls"), 3);
+            panic!("unwind");
+        }));
+        assert!(result.is_err());
+        assert_eq!(store.get_vec("calls").unwrap(), vec!["f", "f"]);
+    }
+
+    #[test]
+    fn test_push_scoped_rejected() {
+        let store = StackStore::new();
+        store.set_capacity(
+            "k",
+            Some(CapacityLimit::new(1, OverflowPolicy::RejectNewest)),
+        );
+        store.set("k", "a".into());
+        let guard = store.push_scoped("k", "b".into());
+        assert_eq!(guard.seq(), None);
+        drop(guard);
+        assert_eq!(store.get_vec("k").unwrap(), vec!["a"]);
+    }
+
+    #[test]
+    fn test_journal_scoped_remove() {
+        let path = temp_path("scoped.journal");
+        let store = StackStore::new().with_rle();
+        store.open_journal(&path).unwrap();
+        store.set("k", "x".into());
+        let guard = store.push_scoped("k", "x".into());
+        store.set("k", "y".into());
+        drop(guard);
+        store.close_journal();
+
+        let restored = StackStore::new().with_rle();
+        assert_eq!(restored.open_journal(&path).unwrap(), 4);
+        assert_eq!(restored.get_vec("k").unwrap(), vec!["x", "y"]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_capture_backtrace() {
+        let store = StackStore::new();
+        let count = store.capture_backtrace_with("native", BacktraceOptions::FULL);
+        assert_eq!(store.len("native"), count);
+        let frames = store.get_vec("native").unwrap_or_default();
+        // 栈顶是最内层的帧
+        assert!(frames
+            .last()
+            .is_some_and(|frame| frame.starts_with("pi_stash::StackStore")));
+        assert!(frames
+            .iter()
+            .any(|frame| frame.starts_with("pi_stash::tests::test_capture_backtrace")));
+
+        store.capture_backtrace("skipped");
+        assert!(store
+            .get_vec("skipped")
+            .unwrap_or_default()
+            .iter()
+            .all(|frame| !frame.starts_with("pi_stash::") && !frame.starts_with("std::rt::")));
+
+        assert_eq!(
+            store.push_backtrace("none", &Backtrace::disabled(), BacktraceOptions::FULL),
+            0
+        );
+        assert_eq!(store.len("none"), 0);
+    }
+
+    #[test]
+    fn test_get_frames() {
+        let store = StackStore::new();
+        store.set(
+            "js",
+            "Error: boom\n    at render (app.js:10:5)\n    at main (app.js:1:1)".into(),
+        );
+        store.set("js", "load@lib.js:3:7".into());
+        let frames = store.get_frames("js").unwrap();
+        let functions: Vec<_> = frames
+            .iter()
+            .map(|frame| frame.function.as_deref().unwrap())
+            .collect();
+        assert_eq!(functions, ["render", "main", "load"]);
+        assert_eq!(frames[2].file.as_deref(), Some("lib.js"));
+        assert_eq!((frames[2].line, frames[2].column), (Some(3), Some(7)));
+        assert_eq!(store.get_frames("missing"), None);
+
+        let interned = StackStore::interned();
+        interned.set_str("js", "at f (a.js:1:2)");
+        assert_eq!(interned.get_frames("js").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_get_symbolized() {
+        let dir = temp_path("maps");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("bundle.js.map"),
+            r#"{"version":3,"sources":["src/app.ts"],"names":["render"],"mappings":"AAAA,UAEIA"}"#,
+        )
+        .unwrap();
+        let symbolizer = Symbolizer::new().with_search_dir(&dir);
+
+        let store = StackStore::new();
+        store.set(
+            "js",
+            "Error\n    at e (http://a.com/bundle.js?v=2:1:15)\n    at http://a.com/bundle.js:9:1\n    \
+             at f (other.js:1:1)\n    at Array.map (native)"
+                .into(),
+        );
+        let frames = store.get_symbolized("js", &symbolizer).unwrap();
+        assert_eq!(frames[0].to_string(), "render (src/app.ts:3:5)");
+        assert!(frames[0].is_resolved());
+        assert_eq!(frames[1].resolution, Resolution::NoMapping);
+        assert_eq!(frames[2].resolution, Resolution::NoSourceMap);
+        assert_eq!(
+            frames[3].to_string(),
+            "Array.map (native) [unresolved: no position]"
+        );
+
+        // 还原导出的JSON
+        let dump = store.iter("js").unwrap();
+        let stacks = symbolizer.symbolize_dump(dump.as_bytes()).unwrap();
+        assert_eq!(stacks, vec![("js".to_string(), frames)]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_symbolizer_retries_failed_maps() {
+        let path = temp_path("retry.js.map");
+        std::fs::write(&path, "{").unwrap();
+        let frame = Frame::parse("at e (retry.js:1:15)").unwrap();
+        let cached = Symbolizer::new()
+            .with_map("retry.js", &path)
+            .with_retry_after(Duration::from_secs(60));
+        let retrying = Symbolizer::new()
+            .with_map("retry.js", &path)
+            .with_retry_after(Duration::ZERO);
+        for symbolizer in [&cached, &retrying] {
+            assert_eq!(
+                symbolizer.symbolize(&frame).resolution,
+                Resolution::NoSourceMap
+            );
+        }
+
+        // 修复文件后，重试时间内不重新读取，重试时间为0时立即重新读取
+        std::fs::write(
+            &path,
+            r#"{"version":3,"sources":["src/app.ts"],"names":["render"],"mappings":"AAAA,UAEIA"}"#,
+        )
+        .unwrap();
+        assert_eq!(cached.symbolize(&frame).resolution, Resolution::NoSourceMap);
+        assert!(retrying.symbolize(&frame).is_resolved());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_fold() {
+        let store = StackStore::new();
+        for key in ["a", "b", "c"] {
+            store.set(key, "main".into());
+            store.set(key, "render".into());
+        }
+        store.set("c", "draw".into());
+        store.set("d", "other".into());
+        store.clear("d");
+        assert_eq!(
+            store.fold("", SampleMode::Key).to_string(),
+            "main;render 2\nmain;render;draw 1\n"
+        );
+
+        let store = StackStore::new().with_rle();
+        let error = "Error\n    at render (app.js:1:2)\n    at main (app.js:3:4)";
+        store.set("js:1", error.into());
+        store.set("js:1", error.into());
+        store.set("js:2", "load@lib.js:3:7".into());
+        store.set("js:2", "not a stack".into());
+        let folded = store.fold("js:", SampleMode::Value);
+        assert_eq!(folded.to_string(), "load 1\nmain;render 2\n");
+
+        let mut svg = Vec::new();
+        folded
+            .write_svg(&mut svg, &FlameGraphOptions::default())
+            .unwrap();
+        assert!(String::from_utf8(svg)
+            .unwrap()
+            .contains("render (2 samples"));
     }
 }
@@ -0,0 +1,70 @@
+// src/limit.rs
+//! 栈容量限制及溢出策略
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 栈超出容量时的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// 丢弃栈底最旧的值（环形缓冲区）
+    DropOldest,
+    /// 拒绝压入新值
+    RejectNewest,
+    /// 清空该键的整个栈，新值作为新栈的第一个元素
+    EvictKey,
+}
+
+/// 单个栈的容量限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityLimit {
+    /// 栈中最多保存的元素个数
+    pub max_len: usize,
+    /// 超出容量时的处理策略
+    pub policy: OverflowPolicy,
+}
+
+impl CapacityLimit {
+    /// 创建容量限制
+    pub fn new(max_len: usize, policy: OverflowPolicy) -> Self {
+        Self { max_len, policy }
+    }
+
+    /// 长度为len的栈能否保存新压入的值
+    pub(crate) fn accepts(&self, len: usize) -> bool {
+        len < self.max_len || (self.max_len > 0 && self.policy != OverflowPolicy::RejectNewest)
+    }
+}
+
+/// 各溢出策略丢弃的值的统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OverflowStats {
+    /// DropOldest策略丢弃的旧值个数
+    pub dropped_oldest: u64,
+    /// RejectNewest策略拒绝的新值个数
+    pub rejected_newest: u64,
+    /// EvictKey策略清空的值个数
+    pub evicted_values: u64,
+    /// EvictKey策略清空栈的次数
+    pub evicted_keys: u64,
+}
+
+/// 溢出统计计数器，由StackStore内部并发累加
+#[derive(Debug, Default)]
+pub(crate) struct OverflowCounters {
+    pub(crate) dropped_oldest: AtomicU64,
+    pub(crate) rejected_newest: AtomicU64,
+    pub(crate) evicted_values: AtomicU64,
+    pub(crate) evicted_keys: AtomicU64,
+}
+
+impl OverflowCounters {
+    /// 读取当前统计值
+    pub(crate) fn snapshot(&self) -> OverflowStats {
+        OverflowStats {
+            dropped_oldest: self.dropped_oldest.load(Ordering::Relaxed),
+            rejected_newest: self.rejected_newest.load(Ordering::Relaxed),
+            evicted_values: self.evicted_values.load(Ordering::Relaxed),
+            evicted_keys: self.evicted_keys.load(Ordering::Relaxed),
+        }
+    }
+}
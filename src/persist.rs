@@ -0,0 +1,253 @@
+// src/persist.rs
+//! 快照持久化及追加写日志
+//!
+//! 日志由连续的记录组成，每条记录为：
+//! 4字节小端负载长度 + 4字节小端负载CRC32 + JSON负载。
+//! 重放时遇到不完整或校验失败的记录，视为崩溃时写了一半的尾部，从该记录起截断
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// 记录头：负载长度 + 负载CRC32
+const HEADER_LEN: usize = 8;
+
+// 单条记录负载的长度上限，超过视为损坏
+const MAX_PAYLOAD_LEN: usize = 1 << 30;
+
+/// 日志中的一条修改操作
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum Record<K, V> {
+    /// 压入一个值
+    Push { key: K, value: V },
+    /// 从栈顶弹出n个值
+    Pop { key: K, n: usize },
+    /// 只保留栈底的n个值
+    Truncate { key: K, n: usize },
+    /// 移除指定位置的一个值，位置从栈底的0开始
+    Remove { key: K, index: usize },
+    /// 移除单独过期的值，每项为(位置, 个数)，按顺序依次移除
+    Expire { key: K, runs: Vec<(usize, usize)> },
+    /// 清空栈
+    Clear { key: K },
+    /// 删除整个栈
+    Delete { key: K },
+}
+
+/// 追加写日志
+pub(crate) struct Journal<K, V> {
+    file: Mutex<File>,
+    path: PathBuf,
+    encode: fn(&Record<&K, &V>) -> serde_json::Result<Vec<u8>>,
+    // 写入失败的记录条数
+    errors: AtomicU64,
+}
+
+impl<K, V> Journal<K, V> {
+    /// 打开日志文件，重放其中的所有完整记录，并截断损坏的尾部
+    ///
+    /// # 返回值
+    /// 日志及重放的记录条数
+    pub(crate) fn open(
+        path: &Path,
+        mut apply: impl FnMut(Record<K, V>),
+    ) -> io::Result<(Self, usize)>
+    where
+        K: Serialize + DeserializeOwned,
+        V: Serialize + DeserializeOwned,
+    {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let (valid_len, count) = replay(&mut file, &mut apply)?;
+        if file.metadata()?.len() > valid_len {
+            file.set_len(valid_len)?;
+        }
+        file.seek(SeekFrom::End(0))?;
+        let journal = Self {
+            file: Mutex::new(file),
+            path: path.to_path_buf(),
+            encode: encode_record::<K, V>,
+            errors: AtomicU64::new(0),
+        };
+        Ok((journal, count))
+    }
+
+    /// 追加一条记录
+    ///
+    /// # 注意
+    /// 写入失败时只累加失败计数，不影响内存中的操作
+    pub(crate) fn append(&self, record: &Record<&K, &V>) {
+        let written = (self.encode)(record)
+            .map_err(io::Error::from)
+            .and_then(|payload| {
+                let frame = frame(&payload);
+                self.file
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .write_all(&frame)
+            });
+        if written.is_err() {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 将日志刷入磁盘
+    pub(crate) fn sync(&self) -> io::Result<()> {
+        self.file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .sync_data()
+    }
+
+    /// 日志文件路径
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 写入失败的记录条数
+    pub(crate) fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+}
+
+fn encode_record<K: Serialize, V: Serialize>(
+    record: &Record<&K, &V>,
+) -> serde_json::Result<Vec<u8>> {
+    serde_json::to_vec(record)
+}
+
+/// 生成带长度和校验和的记录
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&crc32(payload).to_le_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// 从头重放日志中的记录
+///
+/// # 返回值
+/// (完整记录的总长度, 记录条数)
+fn replay<K, V>(file: &mut File, apply: &mut impl FnMut(Record<K, V>)) -> io::Result<(u64, usize)>
+where
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    file.seek(SeekFrom::Start(0))?;
+    let mut reader = BufReader::new(file);
+    let mut offset = 0u64;
+    let mut count = 0;
+    let mut header = [0u8; HEADER_LEN];
+    let mut payload = Vec::new();
+    loop {
+        if !read_full(&mut reader, &mut header)? {
+            break;
+        }
+        let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if len > MAX_PAYLOAD_LEN {
+            break;
+        }
+        payload.resize(len, 0);
+        if !read_full(&mut reader, &mut payload)? || crc32(&payload) != crc {
+            break;
+        }
+        // 校验和正确但无法解析，说明日志与当前键值类型不符，不能截断
+        let record = serde_json::from_slice(&payload)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        apply(record);
+        offset += (HEADER_LEN + len) as u64;
+        count += 1;
+    }
+    Ok((offset, count))
+}
+
+/// 读满缓冲区
+///
+/// # 返回值
+/// - true: 读满
+/// - false: 读到文件末尾，缓冲区未读满
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// 将快照写入临时文件后原子替换目标文件
+pub(crate) fn write_snapshot(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    write(&mut writer)?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+/// 读取快照文件
+pub(crate) fn read_snapshot<K, V>(path: &Path) -> io::Result<Vec<(K, Vec<V>)>>
+where
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    let reader = BufReader::new(File::open(path)?);
+    serde_json::from_reader(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// CRC-32 (IEEE 802.3)
+fn crc32(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut c = i as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 {
+                    0xEDB8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+                k += 1;
+            }
+            table[i] = c;
+            i += 1;
+        }
+        table
+    };
+    !data.iter().fold(!0u32, |crc, &b| {
+        TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
@@ -0,0 +1,48 @@
+// src/rle.rs
+//! 相邻重复值的游程编码
+
+use crate::stack::Stack;
+use serde::{Serialize, Serializer};
+
+/// 游程编码所需的值操作
+pub(crate) struct Rle<V> {
+    // 判断相邻的值是否相同
+    pub(crate) eq: fn(&V, &V) -> bool,
+    // 从合并的值中弹出时复制一份
+    pub(crate) clone: fn(&V) -> V,
+}
+
+impl<V: Clone + PartialEq> Rle<V> {
+    pub(crate) fn new() -> Self {
+        Self {
+            eq: V::eq,
+            clone: V::clone,
+        }
+    }
+}
+
+impl<V> Clone for Rle<V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<V> Copy for Rle<V> {}
+
+/// 按展开后的数组序列化栈内容，不克隆值
+pub(crate) struct ValuesJson<'a, V>(pub(crate) &'a Stack<V>);
+
+impl<V: Serialize> Serialize for ValuesJson<'_, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter())
+    }
+}
+
+/// 按 `[[值, 重复次数], ...]` 的紧凑格式序列化栈内容
+pub(crate) struct CompactJson<'a, V>(pub(crate) &'a Stack<V>);
+
+impl<V: Serialize> Serialize for CompactJson<'_, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.runs().map(|(value, meta)| (value, meta.count)))
+    }
+}
@@ -0,0 +1,122 @@
+// src/scan.rs
+//! 分页遍历
+
+use serde::{Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// 分页遍历的游标，指向上一页最后一个键
+///
+/// 按存储的分片依次遍历，分片内按键名排序，每页只读取返回的键所在的分片，
+/// 不需要从头遍历整个存储。遍历期间新增的键可能不会出现在后续页中，被删除的键会被跳过。
+/// 游标只在创建它的StackStore上有效，可以通过 `to_string`/`parse` 在进程内传递
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ScanCursor {
+    shard: usize,
+    key: String,
+}
+
+impl ScanCursor {
+    pub(crate) fn new(shard: usize, key: &str) -> Self {
+        Self {
+            shard,
+            key: key.to_string(),
+        }
+    }
+
+    /// 游标所在的分片
+    pub(crate) fn shard(&self) -> usize {
+        self.shard
+    }
+
+    /// 分片shard中的键key是否在游标之后
+    pub(crate) fn is_before(&self, shard: usize, key: &str) -> bool {
+        (self.shard, self.key.as_str()) < (shard, key)
+    }
+}
+
+impl fmt::Display for ScanCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.shard, self.key)
+    }
+}
+
+/// 游标字符串格式错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCursorError;
+
+impl fmt::Display for ParseCursorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid scan cursor")
+    }
+}
+
+impl std::error::Error for ParseCursorError {}
+
+impl FromStr for ScanCursor {
+    type Err = ParseCursorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (shard, key) = s.split_once(':').ok_or(ParseCursorError)?;
+        if shard.is_empty() || !shard.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseCursorError);
+        }
+        let shard = shard.parse().map_err(|_| ParseCursorError)?;
+        Ok(Self::new(shard, key))
+    }
+}
+
+impl Serialize for ScanCursor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// 分页遍历的一页结果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ScanPage<K, V> {
+    /// 本页的(键名, 栈内容)，栈内容从栈底到栈顶排列
+    pub items: Vec<(K, Vec<V>)>,
+    /// 下一页的游标，None表示已遍历完毕
+    pub next: Option<ScanCursor>,
+}
+
+/// 按键名排序的候选键
+pub(crate) struct Candidate<K>(pub(crate) K);
+
+impl<K: AsRef<str>> PartialEq for Candidate<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_ref() == other.0.as_ref()
+    }
+}
+
+impl<K: AsRef<str>> Eq for Candidate<K> {}
+
+impl<K: AsRef<str>> PartialOrd for Candidate<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: AsRef<str>> Ord for Candidate<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.as_ref().cmp(other.0.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = ScanCursor::new(12, "server:1:x");
+        let s = cursor.to_string();
+        assert_eq!(s, "12:server:1:x");
+        assert_eq!(s.parse::<ScanCursor>(), Ok(cursor));
+        assert!("zz:k".parse::<ScanCursor>().is_err());
+        assert!("nokey".parse::<ScanCursor>().is_err());
+        assert!(":k".parse::<ScanCursor>().is_err());
+    }
+}
@@ -0,0 +1,59 @@
+// src/scope.rs
+//! 作用域压入
+
+use crate::StackStore;
+use std::hash::Hash;
+
+/// `push_scoped` 返回的守卫，被丢弃时从栈中移除它压入的值
+///
+/// 按压入序号查找并移除自己的值，而不是弹出栈顶，因此多个守卫以任意顺序丢弃都只会移除各自的值；
+/// 值已被弹出、截断、过期或整栈被删除时什么也不做。panic展开时同样会移除
+#[must_use = "丢弃守卫会立即移除压入的值"]
+pub struct ScopeGuard<'a, K, V>
+where
+    K: Hash + Eq,
+{
+    store: &'a StackStore<K, V>,
+    key: K,
+    // 压入的值的序号，None表示值没有被保存
+    seq: Option<u64>,
+}
+
+impl<'a, K, V> ScopeGuard<'a, K, V>
+where
+    K: Hash + Eq,
+{
+    pub(crate) fn new(store: &'a StackStore<K, V>, key: K, seq: Option<u64>) -> Self {
+        Self { store, key, seq }
+    }
+
+    /// 压入的键
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// 压入的值的全局序号，与 `StackEntry::seq` 相同
+    ///
+    /// # 返回值
+    /// None表示栈已满且容量策略丢弃了新值，守卫被丢弃时不做任何事
+    pub fn seq(&self) -> Option<u64> {
+        self.seq
+    }
+
+    /// 值是否仍在栈中
+    pub fn is_active(&self) -> bool {
+        self.seq
+            .is_some_and(|seq| self.store.position_of(&self.key, seq).is_some())
+    }
+}
+
+impl<K, V> Drop for ScopeGuard<'_, K, V>
+where
+    K: Hash + Eq,
+{
+    fn drop(&mut self) {
+        if let Some(seq) = self.seq {
+            self.store.remove_seq(&self.key, seq);
+        }
+    }
+}
@@ -0,0 +1,440 @@
+// src/sourcemap.rs
+//! 按source map v3还原压缩后的JS调用栈
+
+use crate::frame::Frame;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+// source map加载失败后默认多久内不再重试
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// 解码后的source map v3
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    // 已拼接sourceRoot的源文件
+    sources: Vec<String>,
+    names: Vec<String>,
+    // 每个生成行的映射，按生成列排序
+    lines: Vec<Vec<Mapping>>,
+}
+
+/// 生成代码中一个位置的映射，位置均从0开始
+#[derive(Debug, Clone, Copy)]
+struct Mapping {
+    column: u32,
+    // (源文件, 行, 列, 名称)，None表示该位置没有对应的源代码
+    source: Option<(u32, u32, u32, Option<u32>)>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSourceMap {
+    version: u32,
+    #[serde(default)]
+    source_root: Option<String>,
+    #[serde(default)]
+    sources: Vec<Option<String>>,
+    #[serde(default)]
+    names: Vec<String>,
+    mappings: String,
+}
+
+impl SourceMap {
+    /// 解析source map v3的JSON文本
+    ///
+    /// # 注意
+    /// 不支持带 `sections` 的索引映射
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let raw: RawSourceMap = serde_json::from_str(json).map_err(invalid_data)?;
+        if raw.version != 3 {
+            return Err(invalid_data(format!(
+                "unsupported source map version {}",
+                raw.version
+            )));
+        }
+        let root = raw.source_root.unwrap_or_default();
+        let sources = raw
+            .sources
+            .into_iter()
+            .map(|source| {
+                let source = source.unwrap_or_default();
+                if root.is_empty() {
+                    source
+                } else {
+                    format!("{}/{}", root.trim_end_matches('/'), source)
+                }
+            })
+            .collect();
+        Ok(Self {
+            sources,
+            names: raw.names,
+            lines: decode_mappings(&raw.mappings)?,
+        })
+    }
+
+    /// 读取并解析source map文件
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// 查找生成代码中的位置对应的源代码位置
+    ///
+    /// # 参数
+    /// - line: 生成代码的行号，从1开始
+    /// - column: 生成代码的列号，从1开始
+    ///
+    /// # 返回值
+    /// 源代码位置的(文件, 行号, 列号, 名称)，行号和列号从1开始；
+    /// 取该行中不晚于column的最后一个映射，没有映射或映射没有对应的源代码时返回None
+    pub fn lookup(&self, line: u32, column: u32) -> Option<(&str, u32, u32, Option<&str>)> {
+        let mappings = self.lines.get(line.checked_sub(1)? as usize)?;
+        let column = column.saturating_sub(1);
+        let index = mappings.partition_point(|mapping| mapping.column <= column);
+        let (source, line, column, name) = mappings.get(index.checked_sub(1)?)?.source?;
+        Some((
+            self.sources.get(source as usize)?.as_str(),
+            line + 1,
+            column + 1,
+            name.and_then(|name| self.names.get(name as usize).map(String::as_str)),
+        ))
+    }
+}
+
+/// 帧的还原结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    /// 已还原到源代码位置
+    Resolved,
+    /// 帧没有文件或行列号，如 `native` 帧
+    NoPosition,
+    /// 找不到或无法解析该文件的source map
+    NoSourceMap,
+    /// source map中没有该位置的映射
+    NoMapping,
+}
+
+/// 还原后的帧
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SymbolizedFrame {
+    /// 还原后的帧；未还原时与原帧相同
+    pub frame: Frame,
+    /// 还原结果
+    pub resolution: Resolution,
+}
+
+impl SymbolizedFrame {
+    /// 是否已还原
+    pub fn is_resolved(&self) -> bool {
+        self.resolution == Resolution::Resolved
+    }
+}
+
+impl fmt::Display for SymbolizedFrame {
+    /// 已还原的帧与 `Frame` 相同，未还原的帧在末尾标注原因，如 `f (bundle.js:1:5) [unresolved: no source map]`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.frame)?;
+        let reason = match self.resolution {
+            Resolution::Resolved => return Ok(()),
+            Resolution::NoPosition => "no position",
+            Resolution::NoSourceMap => "no source map",
+            Resolution::NoMapping => "no mapping",
+        };
+        write!(f, " [unresolved: {reason}]")
+    }
+}
+
+/// 按source map还原帧，缓存已解码的source map
+///
+/// 帧的文件按以下顺序查找source map：
+/// 1. `with_map` 注册的文件，按完整文件名或去掉路径后的文件名匹配
+/// 2. `with_search_dir` 添加的目录中的 `文件名.map`
+/// 3. 帧的文件是本地路径时，同目录下的 `文件名.map`
+pub struct Symbolizer {
+    maps: HashMap<String, PathBuf>,
+    search_dirs: Vec<PathBuf>,
+    // 加载失败后多久内不再重试
+    retry_after: Duration,
+    // 已解码的source map，加载失败时记录失败的时间
+    cache: Mutex<HashMap<PathBuf, Cached>>,
+}
+
+type Cached = Result<Arc<SourceMap>, Instant>;
+
+impl Default for Symbolizer {
+    fn default() -> Self {
+        Self {
+            maps: HashMap::new(),
+            search_dirs: Vec::new(),
+            retry_after: DEFAULT_RETRY_AFTER,
+            cache: Mutex::default(),
+        }
+    }
+}
+
+impl Symbolizer {
+    /// 创建没有任何source map的还原器
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 为指定的生成文件注册source map
+    ///
+    /// # 参数
+    /// - file: 帧中的文件名或URL，也可以只是文件名，如 `bundle.js`
+    /// - path: 本地source map文件路径
+    pub fn with_map(mut self, file: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        self.maps.insert(file.into(), path.into());
+        self
+    }
+
+    /// 添加查找 `文件名.map` 的本地目录
+    pub fn with_search_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.search_dirs.push(dir.into());
+        self
+    }
+
+    /// 设置source map加载失败后多久内不再重试，默认5秒
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// 还原一帧
+    ///
+    /// # 注意
+    /// 函数名取source map中该位置的名称，没有名称时保留原函数名
+    pub fn symbolize(&self, frame: &Frame) -> SymbolizedFrame {
+        let unresolved = |resolution| SymbolizedFrame {
+            frame: frame.clone(),
+            resolution,
+        };
+        let (Some(file), Some(line)) = (&frame.file, frame.line) else {
+            return unresolved(Resolution::NoPosition);
+        };
+        let Some(map) = self.source_map(file) else {
+            return unresolved(Resolution::NoSourceMap);
+        };
+        let Some((source, line, column, name)) = map.lookup(line, frame.column.unwrap_or(1)) else {
+            return unresolved(Resolution::NoMapping);
+        };
+        SymbolizedFrame {
+            frame: Frame {
+                function: name.map(str::to_string).or_else(|| frame.function.clone()),
+                file: Some(source.to_string()),
+                line: Some(line),
+                column: Some(column),
+            },
+            resolution: Resolution::Resolved,
+        }
+    }
+
+    /// 解析并还原一段调用栈文本，格式见 `Frame::parse`
+    pub fn symbolize_text(&self, text: &str) -> Vec<SymbolizedFrame> {
+        Frame::parse_stack(text)
+            .iter()
+            .map(|frame| self.symbolize(frame))
+            .collect()
+    }
+
+    /// 还原 `iter`、`save_to` 或 `dump` 导出的JSON中的所有栈
+    ///
+    /// # 返回值
+    /// 每个元素是[键名, 还原后的帧]，`dump` 中因锁被占用而没有内容的栈返回空数组
+    pub fn symbolize_dump(
+        &self,
+        reader: impl Read,
+    ) -> io::Result<Vec<(String, Vec<SymbolizedFrame>)>> {
+        let stacks: Vec<(String, Option<Vec<String>>)> =
+            serde_json::from_reader(reader).map_err(invalid_data)?;
+        Ok(stacks
+            .into_iter()
+            .map(|(key, values)| {
+                let frames = values
+                    .iter()
+                    .flatten()
+                    .flat_map(|value| self.symbolize_text(value))
+                    .collect();
+                (key, frames)
+            })
+            .collect())
+    }
+
+    /// 清空已加载的source map缓存，文件更新后调用
+    pub fn clear_cache(&self) {
+        self.cache().clear();
+    }
+
+    /// 查找并加载文件对应的source map
+    ///
+    /// 读取和解码时不持有缓存锁，多个线程同时加载同一个文件时以先完成的为准
+    fn source_map(&self, file: &str) -> Option<Arc<SourceMap>> {
+        let path = self.map_path(file)?;
+        match self.cache().get(&path) {
+            Some(Ok(map)) => return Some(Arc::clone(map)),
+            Some(Err(failed_at)) if failed_at.elapsed() < self.retry_after => return None,
+            _ => {}
+        }
+        let loaded = SourceMap::load(&path)
+            .map(Arc::new)
+            .map_err(|_| Instant::now());
+        let mut cache = self.cache();
+        if let Some(Ok(map)) = cache.get(&path) {
+            return Some(Arc::clone(map));
+        }
+        cache.insert(path, loaded.clone());
+        loaded.ok()
+    }
+
+    /// 文件对应的source map路径
+    fn map_path(&self, file: &str) -> Option<PathBuf> {
+        // 去掉URL的查询参数和片段
+        let file = file.split(['?', '#']).next().unwrap_or(file);
+        let name = file.rsplit(['/', '\\']).next().unwrap_or(file);
+        if let Some(path) = self.maps.get(file).or_else(|| self.maps.get(name)) {
+            return Some(path.clone());
+        }
+        let map_name = format!("{name}.map");
+        if let Some(path) = self
+            .search_dirs
+            .iter()
+            .map(|dir| dir.join(&map_name))
+            .find(|path| path.is_file())
+        {
+            return Some(path);
+        }
+        let local = file.strip_prefix("file://").unwrap_or(file);
+        if local.contains("://") {
+            return None;
+        }
+        let path = PathBuf::from(format!("{local}.map"));
+        path.is_file().then_some(path)
+    }
+
+    fn cache(&self) -> MutexGuard<'_, HashMap<PathBuf, Cached>> {
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// 解码mappings字段
+fn decode_mappings(mappings: &str) -> io::Result<Vec<Vec<Mapping>>> {
+    // 除生成列外，各字段都相对于上一个映射
+    let (mut source, mut line, mut column, mut name) = (0i64, 0i64, 0i64, 0i64);
+    let mut lines = Vec::new();
+    for text in mappings.split(';') {
+        let mut generated = 0i64;
+        let mut mappings = Vec::new();
+        for segment in text.split(',').filter(|segment| !segment.is_empty()) {
+            let fields = decode_vlq(segment)?;
+            generated += fields[0];
+            let mapping_source = match fields.len() {
+                1 => None,
+                4 | 5 => {
+                    source += fields[1];
+                    line += fields[2];
+                    column += fields[3];
+                    let mapping_name = (fields.len() == 5).then(|| {
+                        name += fields[4];
+                        name
+                    });
+                    Some((
+                        to_u32(source)?,
+                        to_u32(line)?,
+                        to_u32(column)?,
+                        mapping_name.map(to_u32).transpose()?,
+                    ))
+                }
+                _ => return Err(invalid_data("invalid source map segment")),
+            };
+            mappings.push(Mapping {
+                column: to_u32(generated)?,
+                source: mapping_source,
+            });
+        }
+        mappings.sort_by_key(|mapping| mapping.column);
+        lines.push(mappings);
+    }
+    Ok(lines)
+}
+
+/// 解码一个映射中的Base64 VLQ数值
+fn decode_vlq(segment: &str) -> io::Result<Vec<i64>> {
+    let mut values = Vec::with_capacity(5);
+    let (mut value, mut shift) = (0i64, 0u32);
+    for b in segment.bytes() {
+        let digit = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(invalid_data("invalid base64 vlq digit")),
+        } as i64;
+        if shift > 60 {
+            return Err(invalid_data("base64 vlq value overflow"));
+        }
+        value |= (digit & 0x1f) << shift;
+        if digit & 0x20 != 0 {
+            shift += 5;
+            continue;
+        }
+        // 最低位是符号位
+        values.push(if value & 1 != 0 {
+            -(value >> 1)
+        } else {
+            value >> 1
+        });
+        (value, shift) = (0, 0);
+    }
+    if shift != 0 {
+        return Err(invalid_data("truncated base64 vlq value"));
+    }
+    Ok(values)
+}
+
+fn to_u32(value: i64) -> io::Result<u32> {
+    u32::try_from(value).map_err(|_| invalid_data("source map position out of range"))
+}
+
+fn invalid_data(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_vlq() {
+        assert_eq!(decode_vlq("AAAA").unwrap(), vec![0, 0, 0, 0]);
+        assert_eq!(decode_vlq("CADE").unwrap(), vec![1, 0, -1, 2]);
+        assert_eq!(decode_vlq("gB").unwrap(), vec![16]);
+        assert_eq!(decode_vlq("2HwcqxB").unwrap(), vec![123, 456, 789]);
+        assert!(decode_vlq("g").is_err());
+        assert!(decode_vlq("A*").is_err());
+    }
+
+    #[test]
+    fn test_lookup() {
+        // 第1行：列0 -> a.js 1:1；列10 -> a.js 3:5 名称render；第2行：列4 -> b.js 2:1
+        let map = SourceMap::from_json(
+            r#"{"version":3,"sourceRoot":"src","sources":["a.js","b.js"],
+                "names":["render"],"mappings":"AAAA,UAEIA;ICDJ"}"#,
+        )
+        .unwrap();
+        assert_eq!(map.lookup(1, 1), Some(("src/a.js", 1, 1, None)));
+        assert_eq!(map.lookup(1, 9), Some(("src/a.js", 1, 1, None)));
+        assert_eq!(map.lookup(1, 20), Some(("src/a.js", 3, 5, Some("render"))));
+        assert_eq!(map.lookup(2, 5), Some(("src/b.js", 2, 1, None)));
+        assert_eq!(map.lookup(2, 1), None);
+        assert_eq!(map.lookup(3, 1), None);
+        assert!(SourceMap::from_json(r#"{"version":2,"mappings":""}"#).is_err());
+    }
+}
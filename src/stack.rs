@@ -0,0 +1,435 @@
+// src/stack.rs
+//! 单个键对应的栈
+
+use crate::entry::{EntryMeta, StackEntry};
+use crate::limit::{CapacityLimit, OverflowCounters, OverflowPolicy};
+use crate::rle::Rle;
+use std::collections::VecDeque;
+use std::ops::Deref;
+use std::sync::atomic::Ordering;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+
+/// 单个键对应的栈，栈底在前、栈顶在后
+///
+/// 值和元数据分开存放在两个等长的队列中，以便按切片借用所有值。
+/// 开启游程编码时，相邻的相同值只保存一份，重复次数记录在元数据的 `count` 中
+pub(crate) struct Stack<V> {
+    pub(crate) values: VecDeque<V>,
+    pub(crate) metas: VecDeque<EntryMeta>,
+    // 值的个数，包括游程编码合并的重复值
+    len: usize,
+    // 整个栈的过期时间，None表示不过期
+    pub(crate) expires_at: Option<Instant>,
+    // 栈中最早过期的值的过期时间，只会早于或等于实际值
+    next_expiry: Option<Instant>,
+    // 键的估算字节数
+    key_bytes: usize,
+    // 所有值的估算字节数之和，合并的重复值只计算一次
+    value_bytes: usize,
+    // 最近一次读写和最近一次写入的逻辑时钟
+    pub(crate) last_used: u64,
+    pub(crate) last_write: u64,
+    // 游程编码，None表示不合并相同的值
+    rle: Option<Rle<V>>,
+}
+
+impl<V> Stack<V> {
+    /// 创建空栈
+    pub(crate) fn new(key_bytes: usize, tick: u64, rle: Option<Rle<V>>) -> Self {
+        Self {
+            values: VecDeque::new(),
+            metas: VecDeque::new(),
+            len: 0,
+            expires_at: None,
+            next_expiry: None,
+            key_bytes,
+            value_bytes: 0,
+            last_used: tick,
+            last_write: tick,
+            rle,
+        }
+    }
+
+    /// 值的个数
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 栈是否为空
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 按栈底到栈顶的顺序遍历所有值，合并的重复值展开
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &V> {
+        self.runs()
+            .flat_map(|(value, meta)| std::iter::repeat_n(value, meta.count))
+    }
+
+    /// 按栈底到栈顶的顺序遍历所有(值, 元数据)，重复次数见元数据的 `count`
+    pub(crate) fn runs(&self) -> impl DoubleEndedIterator<Item = (&V, &EntryMeta)> {
+        self.values.iter().zip(&self.metas)
+    }
+
+    /// 键和所有值的估算字节数
+    pub(crate) fn bytes(&self) -> usize {
+        self.key_bytes + self.value_bytes
+    }
+
+    /// 整个栈是否已过期
+    pub(crate) fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+
+    /// 是否可能有值需要检查过期
+    pub(crate) fn may_expire(&self) -> bool {
+        self.expires_at.is_some() || self.next_expiry.is_some()
+    }
+
+    /// 移除所有已过期的值
+    ///
+    /// # 返回值
+    /// 被移除的(位置, 个数)，按从栈底到栈顶的顺序依次移除即可得到移除后的栈，
+    /// 位置从栈底的0开始，是移除前面的值之后的位置
+    pub(crate) fn purge(&mut self, now: Instant, size: fn(&V) -> usize) -> Vec<(usize, usize)> {
+        let mut removed: Vec<(usize, usize)> = Vec::new();
+        if self.next_expiry.is_none_or(|at| at > now) {
+            return removed;
+        }
+        let values = std::mem::take(&mut self.values);
+        let metas = std::mem::take(&mut self.metas);
+        // 已保留的值的个数，即下一个被移除的值的位置
+        let mut kept = 0;
+        self.next_expiry = None;
+        for (value, meta) in values.into_iter().zip(metas) {
+            match meta.expires_at() {
+                Some(at) if at <= now => {
+                    self.value_bytes -= size(&value);
+                    self.len -= meta.count;
+                    match removed.last_mut() {
+                        Some((index, count)) if *index == kept => *count += meta.count,
+                        _ => removed.push((kept, meta.count)),
+                    }
+                }
+                expires_at => {
+                    if let Some(at) = expires_at {
+                        self.next_expiry = Some(self.next_expiry.map_or(at, |next| next.min(at)));
+                    }
+                    kept += meta.count;
+                    self.values.push_back(value);
+                    self.metas.push_back(meta);
+                }
+            }
+        }
+        removed
+    }
+
+    /// 按容量限制压入新值
+    ///
+    /// # 返回值
+    /// - true: 新值已保存
+    /// - false: 新值被丢弃
+    pub(crate) fn push(
+        &mut self,
+        value: V,
+        meta: EntryMeta,
+        limit: Option<CapacityLimit>,
+        size: fn(&V) -> usize,
+        counters: &OverflowCounters,
+    ) -> bool {
+        let Some(limit) = limit else {
+            self.push_back(value, meta, size);
+            return true;
+        };
+        if self.len < limit.max_len {
+            self.push_back(value, meta, size);
+            return true;
+        }
+
+        match limit.policy {
+            OverflowPolicy::DropOldest => {
+                self.push_back(value, meta, size);
+                let excess = self.len - limit.max_len;
+                self.drop_front(excess, size);
+                counters
+                    .dropped_oldest
+                    .fetch_add(excess as u64, Ordering::Relaxed);
+                limit.max_len > 0
+            }
+            OverflowPolicy::RejectNewest => {
+                counters.rejected_newest.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            OverflowPolicy::EvictKey => {
+                let mut evicted = self.len;
+                self.clear();
+                let kept = limit.max_len > 0;
+                if kept {
+                    self.push_back(value, meta, size);
+                } else {
+                    evicted += 1;
+                }
+                counters
+                    .evicted_values
+                    .fetch_add(evicted as u64, Ordering::Relaxed);
+                counters.evicted_keys.fetch_add(1, Ordering::Relaxed);
+                kept
+            }
+        }
+    }
+
+    /// 换成同一个键的新空栈，返回原来的栈
+    pub(crate) fn reset(&mut self, tick: u64) -> Self {
+        let (key_bytes, rle) = (self.key_bytes, self.rle);
+        std::mem::replace(self, Self::new(key_bytes, tick, rle))
+    }
+
+    /// 与另一个栈交换所有值，两个栈各自的键和整栈存活时间不变
+    pub(crate) fn swap_values(&mut self, other: &mut Self) {
+        std::mem::swap(&mut self.values, &mut other.values);
+        std::mem::swap(&mut self.metas, &mut other.metas);
+        std::mem::swap(&mut self.len, &mut other.len);
+        std::mem::swap(&mut self.next_expiry, &mut other.next_expiry);
+        std::mem::swap(&mut self.value_bytes, &mut other.value_bytes);
+    }
+
+    /// 弹出栈顶元素
+    pub(crate) fn pop(&mut self, size: fn(&V) -> usize) -> Option<V> {
+        self.pop_entry(size).map(|(value, _)| value)
+    }
+
+    /// 弹出栈顶元素及其元数据
+    pub(crate) fn pop_entry(&mut self, size: fn(&V) -> usize) -> Option<(V, EntryMeta)> {
+        let meta = self.metas.back_mut()?;
+        self.len -= 1;
+        if meta.count > 1 {
+            // 合并的重复值只有在开启游程编码时才会出现
+            let rle = self.rle.expect("merged values require run-length encoding");
+            meta.count -= 1;
+            let meta = meta.clone_single();
+            let value = self.values.back().map(rle.clone)?;
+            return Some((value, meta));
+        }
+        let value = self.values.pop_back()?;
+        let meta = self.metas.pop_back()?;
+        self.value_bytes -= size(&value);
+        Some((value, meta))
+    }
+
+    /// 替换栈顶的值，保留其元数据
+    ///
+    /// # 返回值
+    /// - true: 已替换
+    /// - false: 栈为空
+    pub(crate) fn replace_top(&mut self, value: V, size: fn(&V) -> usize) -> bool {
+        let Some(meta) = self.metas.back_mut() else {
+            return false;
+        };
+        if meta.count > 1 {
+            // 从合并的重复值中拆出栈顶
+            meta.count -= 1;
+            let meta = meta.clone_single();
+            self.value_bytes += size(&value);
+            self.values.push_back(value);
+            self.metas.push_back(meta);
+            return true;
+        }
+        if let Some(top) = self.values.back_mut() {
+            self.value_bytes = self.value_bytes - size(top) + size(&value);
+            *top = value;
+        }
+        true
+    }
+
+    /// 从栈顶弹出最多n个元素，按弹出顺序排列
+    pub(crate) fn pop_n(&mut self, n: usize, size: fn(&V) -> usize) -> Vec<V> {
+        let mut popped = Vec::with_capacity(n.min(self.len));
+        while popped.len() < n {
+            match self.pop(size) {
+                Some(value) => popped.push(value),
+                None => break,
+            }
+        }
+        popped
+    }
+
+    /// 只保留栈底的n个元素，返回被移除的元素个数
+    pub(crate) fn truncate(&mut self, n: usize, size: fn(&V) -> usize) -> usize {
+        let removed = self.len.saturating_sub(n);
+        let mut excess = removed;
+        while excess > 0 {
+            let Some(meta) = self.metas.back_mut() else {
+                break;
+            };
+            if meta.count > excess {
+                meta.count -= excess;
+                break;
+            }
+            excess -= meta.count;
+            self.metas.pop_back();
+            if let Some(value) = self.values.pop_back() {
+                self.value_bytes -= size(&value);
+            }
+        }
+        self.len -= removed;
+        removed
+    }
+
+    /// 按全局压入序号查找值的位置，栈底为0
+    pub(crate) fn position(&self, seq: u64) -> Option<usize> {
+        let mut index = 0;
+        for meta in &self.metas {
+            if meta.seq == seq {
+                return Some(index);
+            }
+            index += meta.count;
+        }
+        None
+    }
+
+    /// 移除指定位置的一个值，位置从栈底的0开始
+    ///
+    /// # 返回值
+    /// - true: 已移除
+    /// - false: 位置超出栈的长度
+    pub(crate) fn remove(&mut self, index: usize, size: fn(&V) -> usize) -> bool {
+        let mut start = 0;
+        for run in 0..self.metas.len() {
+            let count = self.metas[run].count;
+            if index < start + count {
+                self.len -= 1;
+                if count > 1 {
+                    self.metas[run].count -= 1;
+                } else if let Some(value) = self.values.remove(run) {
+                    self.metas.remove(run);
+                    self.value_bytes -= size(&value);
+                }
+                return true;
+            }
+            start += count;
+        }
+        false
+    }
+
+    /// 清空栈
+    pub(crate) fn clear(&mut self) {
+        self.values.clear();
+        self.metas.clear();
+        self.len = 0;
+        self.value_bytes = 0;
+        self.next_expiry = None;
+    }
+
+    /// 克隆所有值及其元数据，栈底在前
+    pub(crate) fn entries(&self) -> Vec<StackEntry<V>>
+    where
+        V: Clone,
+    {
+        self.runs()
+            .flat_map(|(value, meta)| {
+                std::iter::repeat_n((value, meta), meta.count)
+                    .map(|(value, meta)| meta.to_entry(value.clone()))
+            })
+            .collect()
+    }
+
+    /// 克隆栈顶的最多n个值，栈底在前
+    pub(crate) fn top(&self, n: usize) -> Vec<V>
+    where
+        V: Clone,
+    {
+        let mut top = Vec::with_capacity(n.min(self.len));
+        for (value, meta) in self.runs().rev() {
+            let take = meta.count.min(n - top.len());
+            top.extend(std::iter::repeat_n(value, take).cloned());
+            if top.len() == n {
+                break;
+            }
+        }
+        top.reverse();
+        top
+    }
+
+    /// 以连续切片的形式访问栈内容
+    ///
+    /// 没有合并的重复值时直接借用，否则展开为新的数组
+    pub(crate) fn as_slice(&mut self) -> Values<'_, V> {
+        match self.rle {
+            Some(rle) if self.len != self.values.len() => {
+                Values::Expanded(self.iter().map(rle.clone).collect())
+            }
+            _ => Values::Borrowed(self.values.make_contiguous()),
+        }
+    }
+
+    fn push_back(&mut self, value: V, meta: EntryMeta, size: fn(&V) -> usize) {
+        self.len += 1;
+        if let (Some(rle), Some(top), Some(top_meta)) =
+            (self.rle, self.values.back(), self.metas.back_mut())
+        {
+            if top_meta.can_merge(&meta) && (rle.eq)(top, &value) {
+                // 整个游程按最后一个值的压入时间过期
+                top_meta.pushed_at = meta.pushed_at;
+                top_meta.count += 1;
+                return;
+            }
+        }
+        if let Some(at) = meta.expires_at() {
+            self.next_expiry = Some(self.next_expiry.map_or(at, |next| next.min(at)));
+        }
+        self.value_bytes += size(&value);
+        self.values.push_back(value);
+        self.metas.push_back(meta);
+    }
+
+    /// 从栈底移除n个值
+    fn drop_front(&mut self, mut n: usize, size: fn(&V) -> usize) {
+        self.len -= n;
+        while n > 0 {
+            let Some(meta) = self.metas.front_mut() else {
+                break;
+            };
+            if meta.count > n {
+                meta.count -= n;
+                break;
+            }
+            n -= meta.count;
+            self.metas.pop_front();
+            if let Some(value) = self.values.pop_front() {
+                self.value_bytes -= size(&value);
+            }
+        }
+    }
+}
+
+/// 借用或展开的栈内容
+pub(crate) enum Values<'a, V> {
+    Borrowed(&'a [V]),
+    Expanded(Vec<V>),
+}
+
+impl<V> Deref for Values<'_, V> {
+    type Target = [V];
+
+    fn deref(&self) -> &[V] {
+        match self {
+            Values::Borrowed(values) => values,
+            Values::Expanded(values) => values,
+        }
+    }
+}
+
+/// 获取栈的互斥锁，锁被污染时从中恢复数据
+pub(crate) fn lock_stack<V>(stack: &Mutex<Stack<V>>) -> MutexGuard<'_, Stack<V>> {
+    stack
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// 取出已从存储中移除的栈，锁被污染时从中恢复数据
+pub(crate) fn into_stack<V>(stack: Mutex<Stack<V>>) -> Stack<V> {
+    stack
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,187 @@
+// src/txn.rs
+//! 多键事务
+
+use crate::entry::EntryMeta;
+use crate::persist::Record;
+use crate::stack::{Stack, Values};
+use crate::{Access, Event, StackStore};
+use std::borrow::{Borrow, Cow};
+use std::collections::BTreeMap;
+use std::hash::Hash;
+
+/// 事务中已锁定的一组栈
+///
+/// 通过 `StackStore::transaction` 获得；事务执行期间其它线程对这些键的访问会等待，
+/// 因此读取单个键时要么看到事务之前的状态，要么看到事务完成后的状态
+///
+/// # 注意
+/// 所有方法只能访问开启事务时指定的键，访问其它键会panic
+pub struct Transaction<'a, K, V> {
+    store: &'a StackStore<K, V>,
+    // 按键排序的已锁定的栈
+    stacks: Vec<(&'a K, &'a mut Stack<V>)>,
+    // 是否压入过值或有栈从空变为非空，事务结束后需要唤醒等待者
+    pub(crate) pushed: bool,
+}
+
+impl<'a, K, V> Transaction<'a, K, V>
+where
+    K: Hash + Eq,
+{
+    pub(crate) fn new(store: &'a StackStore<K, V>, stacks: Vec<(&'a K, &'a mut Stack<V>)>) -> Self {
+        Self {
+            store,
+            stacks,
+            pushed: false,
+        }
+    }
+
+    /// 将值压入指定键的栈顶，与 `StackStore::set` 相同
+    ///
+    /// # 返回值
+    /// - true: 值已保存
+    /// - false: 栈已满且容量策略丢弃了新值
+    pub fn push<Q>(&mut self, key: &Q, value: V) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let meta = EntryMeta::new(self.store.default_ttl(), BTreeMap::new());
+        self.push_meta(key, value, meta)
+    }
+
+    /// 弹出指定键的栈顶元素
+    pub fn pop<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.pop_meta(key).map(|(value, _)| value)
+    }
+
+    /// 查看指定键的栈顶元素
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.stacks[self.index(key)].1.values.back()
+    }
+
+    /// 获取指定键的栈中元素个数
+    pub fn len<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.stacks[self.index(key)].1.len()
+    }
+
+    /// 以切片的形式访问指定键的整个栈，栈底在前
+    ///
+    /// 开启游程编码且有合并的重复值时返回展开后的副本
+    pub fn values<Q>(&mut self, key: &Q) -> Cow<'_, [V]>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        let i = self.index(key);
+        match self.stacks[i].1.as_slice() {
+            Values::Borrowed(values) => Cow::Borrowed(values),
+            Values::Expanded(values) => Cow::Owned(values),
+        }
+    }
+
+    /// 交换两个键的栈内容
+    ///
+    /// # 注意
+    /// 值连同其元数据一起交换；各键的容量限制和整栈存活时间不随之交换
+    pub fn swap<Q>(&mut self, a: &Q, b: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (i, j) = (self.index(a), self.index(b));
+        if i == j {
+            return;
+        }
+        let (lo, hi) = self.stacks.split_at_mut(i.max(j));
+        let (a, b) = (&mut lo[i.min(j)], &mut hi[0]);
+        let (len_a, len_b) = (a.1.len(), b.1.len());
+        a.1.swap_values(b.1);
+        // 有一侧从空变为非空时，事务结束后唤醒等待弹出的线程
+        self.pushed |= (len_a == 0) != (len_b == 0);
+        for (key, stack, old_len) in [(a.0, &mut *a.1, len_a), (b.0, &mut *b.1, len_b)] {
+            self.store.update(stack, Access::Write, |_| ());
+            self.store.log(|| Record::Clear { key });
+            if old_len > 0 {
+                self.store.listeners.emit(|| Event::Popped {
+                    key,
+                    count: old_len,
+                });
+            }
+            for value in stack.iter() {
+                self.store.log(|| Record::Push { key, value });
+                self.store.listeners.emit(|| Event::Pushed { key, value });
+            }
+        }
+    }
+
+    /// 按容量限制，指定键的栈能否保存新压入的值
+    pub(crate) fn accepts<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store
+            .capacity(key)
+            .is_none_or(|limit| limit.accepts(self.len(key)))
+    }
+
+    /// 压入带元数据的值
+    pub(crate) fn push_meta<Q>(&mut self, key: &Q, value: V, meta: EntryMeta) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let i = self.index(key);
+        let limit = self.store.capacity(key);
+        let (key, stack) = &mut self.stacks[i];
+        let store = self.store;
+        let pushed = store.update(stack, Access::Write, |stack| {
+            store.push_value(key, stack, value, meta, limit)
+        });
+        self.pushed |= pushed;
+        pushed
+    }
+
+    /// 弹出栈顶的值及其元数据
+    pub(crate) fn pop_meta<Q>(&mut self, key: &Q) -> Option<(V, EntryMeta)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let i = self.index(key);
+        let size = self.store.sizer.value;
+        let (key, stack) = &mut self.stacks[i];
+        let key: &K = key;
+        let store = self.store;
+        let popped = store.update(stack, Access::Write, |stack| stack.pop_entry(size))?;
+        store.log(|| Record::Pop { key, n: 1 });
+        store.listeners.emit(|| Event::Popped { key, count: 1 });
+        Some(popped)
+    }
+
+    /// 键在已锁定的栈中的位置
+    fn index<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.stacks
+            .iter()
+            .position(|(k, _)| (*k).borrow() == key)
+            .expect("key is not part of the transaction")
+    }
+}
@@ -0,0 +1,130 @@
+// src/wait.rs
+//! 等待值压入
+
+use crate::StackStore;
+use std::borrow::Borrow;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+
+/// 等待压入的线程和任务
+///
+/// 不区分键：任何键压入新值都会唤醒所有等待者，由等待者重新尝试弹出
+#[derive(Default)]
+pub(crate) struct Waiters {
+    // 等待中的线程和任务个数，为0时压入不需要唤醒
+    pub(crate) count: AtomicUsize,
+    // 等待中的异步任务
+    wakers: Mutex<Vec<Waker>>,
+    condvar: Condvar,
+}
+
+impl Waiters {
+    /// 压入新值后唤醒所有等待者
+    pub(crate) fn notify(&self) {
+        if self.count.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        // 先取得锁，保证等待者在检查与睡眠之间不会错过通知
+        let wakers = std::mem::take(&mut *self.lock());
+        self.condvar.notify_all();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// 反复尝试f，直到其返回Some或超过截止时间
+    ///
+    /// 检查与睡眠之间持有锁，不会错过通知
+    pub(crate) fn wait_until<T>(
+        &self,
+        deadline: Instant,
+        mut f: impl FnMut() -> Option<T>,
+    ) -> Option<T> {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        let mut guard = self.lock();
+        let value = loop {
+            if let Some(value) = f() {
+                break Some(value);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break None;
+            }
+            guard = self
+                .condvar
+                .wait_timeout(guard, deadline - now)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        };
+        drop(guard);
+        self.count.fetch_sub(1, Ordering::SeqCst);
+        value
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Waker>> {
+        self.wakers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// `pop_async` 返回的Future
+pub struct PopFuture<'a, K, V, Q: ?Sized> {
+    store: &'a StackStore<K, V>,
+    key: &'a Q,
+    // 是否已计入等待者个数
+    waiting: bool,
+}
+
+impl<'a, K, V, Q: ?Sized> PopFuture<'a, K, V, Q> {
+    pub(crate) fn new(store: &'a StackStore<K, V>, key: &'a Q) -> Self {
+        Self {
+            store,
+            key,
+            waiting: false,
+        }
+    }
+}
+
+impl<K, V, Q> Future for PopFuture<'_, K, V, Q>
+where
+    K: Hash + Eq + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+{
+    type Output = V;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<V> {
+        let waiters = &self.store.waiters;
+        if !self.waiting {
+            if let Some(value) = self.store.pop(self.key) {
+                return Poll::Ready(value);
+            }
+            waiters.count.fetch_add(1, Ordering::SeqCst);
+            self.waiting = true;
+        }
+        let mut wakers = waiters.lock();
+        if let Some(value) = self.store.pop(self.key) {
+            drop(wakers);
+            waiters.count.fetch_sub(1, Ordering::SeqCst);
+            self.waiting = false;
+            return Poll::Ready(value);
+        }
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl<K, V, Q: ?Sized> Drop for PopFuture<'_, K, V, Q> {
+    fn drop(&mut self) {
+        if self.waiting {
+            self.store.waiters.count.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}